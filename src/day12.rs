@@ -1,8 +1,8 @@
-use std::{vec, collections::VecDeque};
+use std::{vec, cmp::{max, min, Reverse}, collections::{BinaryHeap, VecDeque}, fmt, rc::Rc};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -12,6 +12,23 @@ pub struct Data {
     grid: Vec<Vec<usize>> // the heightmap
 }
 
+impl Data {
+    /// Where S is, as (row, col)
+    pub fn start(&self) -> (usize, usize) {
+        self.start
+    }
+
+    /// Where E is, as (row, col)
+    pub fn end(&self) -> (usize, usize) {
+        self.end
+    }
+
+    /// The elevations, 0 for a up to 25 for z
+    pub fn grid(&self) -> &[Vec<usize>] {
+        &self.grid
+    }
+}
+
 // Puzzle input is a grid of letters
 // Lowercase a-z indicates elevation (increasing from a to z)
 // S is start (elevation a) and E is end (elevation z)
@@ -20,31 +37,52 @@ pub fn input_generator(input: &str) -> Result<Data> {
     input_generator_inner(input)
 }
 fn input_generator_inner(input: &str) -> Result<Data> {
-    let mut start = (0, 0);
-    let mut end= (0, 0);
+    let mut start = None;
+    let mut end = None;
     let mut grid = vec![];
 
+    // line and column numbers in errors count from 1, like an editor would
     for (i, line) in input.lines().enumerate(){
         let mut row = vec![];
-        for (j, c) in line.as_bytes().iter().enumerate() {
+        for (j, c) in line.chars().enumerate() {
             let elevation = match c {
-                b'S' => {
-                    start = (i, j);
+                'S' => {
+                    if let Some((row, col)) = start.replace((i, j)) {
+                        return Err(anyhow!("line {}, column {}: second S (the first is at line {}, column {})",
+                            i + 1, j + 1, row + 1, col + 1));
+                    }
                     0
                 },
-                b'E' => {
-                    end = (i, j);
+                'E' => {
+                    if let Some((row, col)) = end.replace((i, j)) {
+                        return Err(anyhow!("line {}, column {}: second E (the first is at line {}, column {})",
+                            i + 1, j + 1, row + 1, col + 1));
+                    }
                     25
                 },
-                b'a'..=b'z' => (c - b'a') as usize,
-                _ => unreachable!()
+                'a'..='z' => c as usize - 'a' as usize,
+                _ => return Err(anyhow!("line {}, column {}: {:?} isn't an elevation, S or E", i + 1, j + 1, c)),
             };
             row.push(elevation);
         }
+        // every row has to be as long as the first one
+        if let Some(first) = grid.first().map(Vec::len) {
+            if row.len() != first {
+                return Err(anyhow!("line {}: {} squares wide, but line 1 is {}", i + 1, row.len(), first));
+            }
+        }
+        else if row.is_empty() {
+            return Err(anyhow!("line 1: no squares at all"));
+        }
         grid.push(row)
     }
 
-    Ok(Data{start, end, grid})
+    match (start, end) {
+        (Some(start), Some(end)) => Ok(Data{start, end, grid}),
+        (None, _) if grid.is_empty() => Err(anyhow!("the heightmap is empty")),
+        (None, _) => Err(anyhow!("there's no S in the heightmap")),
+        (_, None) => Err(anyhow!("there's no E in the heightmap")),
+    }
 }
 #[derive(Eq, PartialEq)]
 pub struct Node {
@@ -65,18 +103,31 @@ impl PartialOrd for Node {
     }
 }
 
+// Up, Down, Right, Left
+const OFFSETS: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, 1), (0, -1)];
+// and the diagonals too
+const OFFSETS_WITH_DIAGONALS: [(isize, isize); 8] =
+    [(-1, 0), (1, 0), (0, 1), (0, -1), (-1, -1), (-1, 1), (1, -1), (1, 1)];
+
 /// Given a location, elevation grid, and elevation change rule,
 /// return the locations I can get to
-fn get_neighbors<F>(location: (usize, usize), grid: &Vec<Vec<usize>>,
+fn get_neighbors<F>(location: (usize, usize), grid: &[Vec<usize>],
         is_valid_move: &F) 
         -> Vec<(usize, usize)>
-        where F: Fn(&Vec<Vec<usize>>, (usize, usize), (usize, usize))  -> bool
+        where F: Fn(&[Vec<usize>], (usize, usize), (usize, usize))  -> bool
+{
+    get_neighbors_with_offsets(location, grid, &OFFSETS, is_valid_move)
+}
+
+/// Same as get_neighbors, but with a choice of which directions to look in
+fn get_neighbors_with_offsets<F>(location: (usize, usize), grid: &[Vec<usize>],
+        offsets: &[(isize, isize)], is_valid_move: &F) 
+        -> Vec<(usize, usize)>
+        where F: Fn(&[Vec<usize>], (usize, usize), (usize, usize))  -> bool
 {
     let mut neighbors = vec![];
     let num_rows = grid.len() as isize;
     let num_cols = grid[0].len() as isize;
-    // Up, Down, Right, Left
-    let offsets = [(-1, 0), (1, 0), (0, 1), (0, -1)];
     for offset in offsets {
         // needs to be isize in order to check for -1
         let row = (location.0 as isize) + offset.0;
@@ -91,17 +142,40 @@ fn get_neighbors<F>(location: (usize, usize), grid: &Vec<Vec<usize>>,
     neighbors
 }
 
+// the puzzle's rule, as a closure for red_blob: up at most 1
+fn can_climb(grid: &[Vec<usize>], (row, col): (usize, usize), location: (usize, usize)) -> bool {
+    grid[row][col] <= grid[location.0][location.1] + 1
+}
+
+// the same rule backwards, for searching from E
+fn can_climb_down(grid: &[Vec<usize>], (row, col): (usize, usize), location: (usize, usize)) -> bool {
+    grid[location.0][location.1] <= grid[row][col] + 1
+}
+
 /// Given a start location, elevation grid, and elevation change rule
 /// return the distance to every location
-fn red_blob<F>(start: (usize, usize), grid: &Vec<Vec<usize>>, is_valid_move: F) 
+fn red_blob<F>(start: (usize, usize), grid: &[Vec<usize>], is_valid_move: F) 
         -> Vec<Vec<usize>>
-        where F: Fn(&Vec<Vec<usize>>, (usize, usize), (usize, usize))  -> bool
+        where F: Fn(&[Vec<usize>], (usize, usize), (usize, usize))  -> bool
+{
+    red_blob_with_predecessors(start, grid, is_valid_move).0
+}
+
+// for each location, the location we came from to get there (None for the start
+// and anywhere we can't get to)
+type Predecessors = Vec<Vec<Option<(usize, usize)>>>;
+
+/// Same as red_blob, but also remember how we got to every location
+fn red_blob_with_predecessors<F>(start: (usize, usize), grid: &[Vec<usize>], is_valid_move: F) 
+        -> (Vec<Vec<usize>>, Predecessors)
+        where F: Fn(&[Vec<usize>], (usize, usize), (usize, usize))  -> bool
 {
     let num_rows = grid.len();
     let num_cols = grid[0].len();
     // distance from start to each location
     let mut distance = vec![vec![usize::MAX; num_cols]; num_rows];
     distance[start.0][start.1] = 0;
+    let mut predecessors = vec![vec![None; num_cols]; num_rows];
 
     let start_node = Node {
         row: start.0,
@@ -125,56 +199,488 @@ fn red_blob<F>(start: (usize, usize), grid: &Vec<Vec<usize>>, is_valid_move: F)
                 };
                 frontier.push_back(neighbor_node);
                 distance[neighbor.0][neighbor.1] = new_distance;
+                predecessors[neighbor.0][neighbor.1] = Some((node.row, node.col));
+            }
+        }
+    }
+
+    (distance, predecessors)
+}
+
+/// How you're allowed to climb around the heightmap, and what each step costs
+#[derive(Clone)]
+pub struct ClimbRules {
+    // the most a single step can go up
+    pub max_ascent: usize,
+    // the most a single step can go down (None: as far as you like)
+    pub max_descent: Option<usize>,
+    // whether diagonal steps are allowed
+    pub diagonals: bool,
+    // the cost of a step, given the change in elevation (up is positive)
+    // a "reference-counted closure trait object", like the monkeys
+    pub step_cost: Rc<dyn Fn(isize) -> usize>,
+}
+
+// closures can't be printed, so step_cost just shows up as a placeholder
+impl fmt::Debug for ClimbRules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ClimbRules")
+            .field("max_ascent", &self.max_ascent)
+            .field("max_descent", &self.max_descent)
+            .field("diagonals", &self.diagonals)
+            .field("step_cost", &format_args!("<closure>"))
+            .finish()
+    }
+}
+
+impl Default for ClimbRules {
+    /// The puzzle's rules: up at most 1, down any amount, every step costs 1
+    fn default() -> Self {
+        ClimbRules { max_ascent: 1, max_descent: None, diagonals: false, step_cost: Rc::new(|_| 1) }
+    }
+}
+
+impl ClimbRules {
+    /// Is a step from elevation `from` to elevation `to` allowed?
+    pub fn allows(&self, from: usize, to: usize) -> bool {
+        if to >= from {
+            to - from <= self.max_ascent
+        }
+        else {
+            self.max_descent.is_none_or(|max_descent| from - to <= max_descent)
+        }
+    }
+
+    /// The same rules, but every step costs 1
+    pub fn counting_steps(&self) -> Self {
+        ClimbRules { step_cost: Rc::new(|_| 1), ..self.clone() }
+    }
+
+    fn offsets(&self) -> &'static [(isize, isize)] {
+        if self.diagonals {
+            &OFFSETS_WITH_DIAGONALS
+        }
+        else {
+            &OFFSETS
+        }
+    }
+}
+
+/// Dijkstra's algorithm: the cheapest cost from any of the starts to every location
+/// (usize::MAX where we can't get to)
+pub fn dijkstra(starts: &[(usize, usize)], grid: &[Vec<usize>], rules: &ClimbRules) -> Vec<Vec<usize>> {
+    let num_rows = grid.len();
+    let num_cols = grid[0].len();
+    let mut cost = vec![vec![usize::MAX; num_cols]; num_rows];
+
+    // Node's Ord is by distance, so Reverse makes this a min-heap
+    let mut frontier = BinaryHeap::new();
+    for &(row, col) in starts {
+        cost[row][col] = 0;
+        frontier.push(Reverse(Node { row, col, distance: 0 }));
+    }
+
+    let is_valid_move = |grid: &[Vec<usize>], (row, col): (usize, usize), location: (usize, usize)| {
+        rules.allows(grid[location.0][location.1], grid[row][col])
+    };
+    while let Some(Reverse(node)) = frontier.pop() {
+        // already found a cheaper way here
+        if node.distance > cost[node.row][node.col] {
+            continue;
+        }
+        let neighbors = get_neighbors_with_offsets((node.row, node.col), grid, rules.offsets(), &is_valid_move);
+        for neighbor in neighbors {
+            let change = grid[neighbor.0][neighbor.1] as isize - grid[node.row][node.col] as isize;
+            let new_cost = node.distance + (rules.step_cost)(change);
+            if new_cost < cost[neighbor.0][neighbor.1] {
+                cost[neighbor.0][neighbor.1] = new_cost;
+                frontier.push(Reverse(Node { row: neighbor.0, col: neighbor.1, distance: new_cost }));
+            }
+        }
+    }
+
+    cost
+}
+
+/// Everywhere with elevation a (including S)
+pub fn lowest_points(input: &Data) -> Vec<(usize, usize)> {
+    let mut lowest = vec![];
+    for (i, row) in input.grid.iter().enumerate() {
+        for (j, &elevation) in row.iter().enumerate() {
+            if elevation == 0 {
+                lowest.push((i, j));
+            }
+        }
+    }
+    lowest
+}
+
+/// The cheapest way to get from any of the starts to E under these rules
+pub fn cheapest_route(input: &Data, starts: &[(usize, usize)], rules: &ClimbRules) -> Option<usize> {
+    let cost = dijkstra(starts, &input.grid, rules);
+    match cost[input.end.0][input.end.1] {
+        usize::MAX => None,
+        cost => Some(cost),
+    }
+}
+
+/// The fewest steps to get from any of the starts to E under these rules
+pub fn fewest_steps(input: &Data, starts: &[(usize, usize)], rules: &ClimbRules) -> Option<usize> {
+    cheapest_route(input, starts, &rules.counting_steps())
+}
+
+/// Follow the predecessors back from location to wherever the search started
+/// Returns the locations in the order they were visited from location
+fn follow_predecessors(location: (usize, usize), predecessors: &Predecessors) -> Vec<(usize, usize)> {
+    let mut route = vec![location];
+    let mut cur = location;
+    while let Some(prev) = predecessors[cur.0][cur.1] {
+        route.push(prev);
+        cur = prev;
+    }
+    route
+}
+
+/// A shortest route from S to E for part 1, including both ends
+pub fn route_part1(input: &Data) -> Option<Vec<(usize, usize)>> {
+    let (distances, predecessors) = red_blob_with_predecessors(input.start, &input.grid, can_climb);
+    if distances[input.end.0][input.end.1] == usize::MAX {
+        return None;
+    }
+
+    // we followed it backwards from the end
+    let mut route = follow_predecessors(input.end, &predecessors);
+    route.reverse();
+    Some(route)
+}
+
+/// A shortest route from any a to E for part 2, including both ends
+pub fn route_part2(input: &Data) -> Option<Vec<(usize, usize)>> {
+    let (distances, predecessors) = red_blob_with_predecessors(input.end, &input.grid, can_climb_down);
+
+    // the closest a: the search went backwards from the end,
+    // so following the predecessors from here goes forwards
+    let mut closest = None;
+    for (i, row) in input.grid.iter().enumerate() {
+        for (j, &elevation) in row.iter().enumerate() {
+            if elevation == 0 && distances[i][j] != usize::MAX
+                    && closest.is_none_or(|(ci, cj): (usize, usize)| distances[i][j] < distances[ci][cj]) {
+                closest = Some((i, j));
+            }
+        }
+    }
+    closest.map(|location| follow_predecessors(location, &predecessors))
+}
+
+/// Draw a route like the puzzle does: an arrow on each square pointing to the
+/// next one, E at the end and . everywhere else
+pub fn render_route(input: &Data, route: &[(usize, usize)]) -> String {
+    let mut picture = vec![vec!['.'; input.grid[0].len()]; input.grid.len()];
+    for step in route.windows(2) {
+        let (from, to) = (step[0], step[1]);
+        let arrow = if to.0 < from.0 {
+            '^'
+        }
+        else if to.0 > from.0 {
+            'v'
+        }
+        else if to.1 > from.1 {
+            '>'
+        }
+        else {
+            '<'
+        };
+        picture[from.0][from.1] = arrow;
+    }
+    if let Some(&(row, col)) = route.last() {
+        picture[row][col] = 'E';
+    }
+
+    picture.iter().map(|row| row.iter().collect::<String>() + "\n").collect()
+}
+
+/// Read a grayscale netpbm (PGM) image as a heightmap
+/// Pixel values are spread evenly over `levels` elevations (26 for a-z),
+/// black being the lowest. Both the plain (P2) and binary (P5) formats work.
+/// start and end are (row, col)
+pub fn from_pgm(image: &[u8], start: (usize, usize), end: (usize, usize), levels: usize) -> Result<Data> {
+    // the header is whitespace-separated, with # comments to the end of the line
+    let mut pos = 0;
+    let next_token = |pos: &mut usize| -> Result<String> {
+        loop {
+            match image.get(*pos) {
+                Some(b'#') => while !matches!(image.get(*pos), Some(b'\n') | None) {
+                    *pos += 1;
+                },
+                Some(b) if b.is_ascii_whitespace() => *pos += 1,
+                Some(_) => break,
+                None => return Err(anyhow!("PGM ended in the header")),
+            }
+        }
+        let token_start = *pos;
+        while matches!(image.get(*pos), Some(b) if !b.is_ascii_whitespace()) {
+            *pos += 1;
+        }
+        Ok(String::from_utf8_lossy(&image[token_start..*pos]).to_string())
+    };
+
+    let magic = next_token(&mut pos)?;
+    if magic != "P2" && magic != "P5" {
+        return Err(anyhow!("not a PGM image: magic number is {:?}", magic));
+    }
+    let width: usize = next_token(&mut pos)?.parse()?;
+    let height: usize = next_token(&mut pos)?.parse()?;
+    let max_value: usize = next_token(&mut pos)?.parse()?;
+    if width == 0 || height == 0 || max_value == 0 || levels == 0 {
+        return Err(anyhow!("empty PGM image or no elevation levels"));
+    }
+    if max_value > 65535 {
+        return Err(anyhow!("PGM max value {} is over 65535", max_value));
+    }
+    let num_pixels = width.checked_mul(height)
+        .ok_or(anyhow!("{}x{} PGM is too big", width, height))?;
+
+    let values: Vec<usize> = if magic == "P2" {
+        (0..num_pixels).map(|_| Ok(next_token(&mut pos)?.parse()?)).collect::<Result<_>>()?
+    }
+    else {
+        // exactly one whitespace character between the header and the pixels
+        let raster = image.get(pos + 1..).unwrap_or_default();
+        let bytes_per_value = if max_value < 256 { 1 } else { 2 };
+        let num_bytes = num_pixels.checked_mul(bytes_per_value)
+            .ok_or(anyhow!("{}x{} PGM is too big", width, height))?;
+        if raster.len() < num_bytes {
+            return Err(anyhow!("PGM has {} bytes of pixels, expected {}", raster.len(), num_bytes));
+        }
+        raster.chunks(bytes_per_value).take(num_pixels)
+            .map(|value| value.iter().fold(0, |acc, &b| acc * 256 + b as usize))
+            .collect()
+    };
+
+    let mut grid = vec![];
+    for row in values.chunks(width) {
+        grid.push(row.iter()
+            .map(|&value| min(value, max_value).checked_mul(levels)
+                .map(|scaled| scaled / (max_value + 1))
+                .ok_or(anyhow!("{} elevation levels is too many", levels)))
+            .collect::<Result<_>>()?);
+    }
+
+    for (name, (row, col)) in [("start", start), ("end", end)] {
+        if row >= height || col >= width {
+            return Err(anyhow!("{} {:?} is outside the {}x{} image", name, (row, col), width, height));
+        }
+    }
+    Ok(Data{start, end, grid})
+}
+
+/// The fewest steps from S to everywhere, climbing like part 1
+/// (usize::MAX where we can't get to)
+pub fn distance_field(input: &Data) -> Vec<Vec<usize>> {
+    red_blob(input.start, &input.grid, can_climb)
+}
+
+/// Save distances as a binary PGM image: black is close, light gray is far,
+/// and anywhere unreachable is white
+pub fn distances_to_pgm(distances: &[Vec<usize>]) -> Vec<u8> {
+    let height = distances.len();
+    let width = distances.first().map_or(0, |row| row.len());
+    let furthest = distances.iter().flatten()
+        .filter(|&&d| d != usize::MAX)
+        .max().copied().unwrap_or(0)
+        .max(1);
+
+    let mut image = format!("P5\n{} {}\n255\n", width, height).into_bytes();
+    for &d in distances.iter().flatten() {
+        let value = if d == usize::MAX {
+            255
+        }
+        else {
+            // leave 255 for unreachable
+            (d * 254 / furthest) as u8
+        };
+        image.push(value);
+    }
+    image
+}
+
+/// The steepest step on the easiest route, when there's no route at all
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Bottleneck {
+    // how far up you'd have to be allowed to climb in one step to get to E
+    pub climb: usize,
+    // the step that needs it
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+}
+
+/// Everything about who can get where under the puzzle's climbing rule
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ReachabilityReport {
+    // groups of squares that can all get to each other, biggest first
+    pub regions: Vec<Vec<(usize, usize)>>,
+    // whether S can get to E
+    pub start_reaches_end: bool,
+    // the squares with elevation a that can get to E
+    pub lowest_reaching_end: Vec<(usize, usize)>,
+    // what's in the way, if S can't get to E
+    pub bottleneck: Option<Bottleneck>,
+}
+
+/// Squares that can all reach each other (strongly connected components)
+/// Kosaraju: finish order going forwards, then flood backwards in reverse finish order
+fn regions(grid: &[Vec<usize>]) -> Vec<Vec<(usize, usize)>> {
+    let num_rows = grid.len();
+    let num_cols = grid[0].len();
+
+    // depth-first search without recursion, remembering when each square finishes
+    let mut visited = vec![vec![false; num_cols]; num_rows];
+    let mut finished = vec![];
+    for row in 0..num_rows {
+        for col in 0..num_cols {
+            if visited[row][col] {
+                continue;
+            }
+            visited[row][col] = true;
+            let mut stack = vec![((row, col), get_neighbors((row, col), grid, &can_climb))];
+            while let Some((location, neighbors)) = stack.last_mut() {
+                match neighbors.pop() {
+                    Some(next) => if !visited[next.0][next.1] {
+                        visited[next.0][next.1] = true;
+                        let next_neighbors = get_neighbors(next, grid, &can_climb);
+                        stack.push((next, next_neighbors));
+                    },
+                    None => {
+                        finished.push(*location);
+                        stack.pop();
+                    },
+                }
+            }
+        }
+    }
+
+    let mut region_of = vec![vec![None; num_cols]; num_rows];
+    let mut regions = vec![];
+    for &location in finished.iter().rev() {
+        if region_of[location.0][location.1].is_some() {
+            continue;
+        }
+        let region_index = regions.len();
+        region_of[location.0][location.1] = Some(region_index);
+        let mut region = vec![];
+        let mut to_visit = vec![location];
+        while let Some(cur) = to_visit.pop() {
+            region.push(cur);
+            for prev in get_neighbors(cur, grid, &can_climb_down) {
+                if region_of[prev.0][prev.1].is_none() {
+                    region_of[prev.0][prev.1] = Some(region_index);
+                    to_visit.push(prev);
+                }
             }
         }
+        region.sort();
+        regions.push(region);
     }
 
-    distance
+    regions.sort_by_key(|region| Reverse(region.len()));
+    regions
+}
+
+/// The route from S to E that needs the smallest single climb, and its steepest step
+/// Dijkstra again, but a route costs its biggest climb rather than its length
+fn find_bottleneck(input: &Data) -> Bottleneck {
+    let grid = &input.grid;
+    let num_rows = grid.len();
+    let num_cols = grid[0].len();
+    let mut cost = vec![vec![usize::MAX; num_cols]; num_rows];
+    let mut predecessors: Predecessors = vec![vec![None; num_cols]; num_rows];
+    let climb = |from: (usize, usize), to: (usize, usize)| grid[to.0][to.1].saturating_sub(grid[from.0][from.1]);
+
+    let mut frontier = BinaryHeap::new();
+    cost[input.start.0][input.start.1] = 0;
+    frontier.push(Reverse(Node { row: input.start.0, col: input.start.1, distance: 0 }));
+    while let Some(Reverse(node)) = frontier.pop() {
+        if node.distance > cost[node.row][node.col] {
+            continue;
+        }
+        let location = (node.row, node.col);
+        for neighbor in get_neighbors(location, grid, &|_, _, _| true) {
+            let new_cost = max(node.distance, climb(location, neighbor));
+            if new_cost < cost[neighbor.0][neighbor.1] {
+                cost[neighbor.0][neighbor.1] = new_cost;
+                predecessors[neighbor.0][neighbor.1] = Some(location);
+                frontier.push(Reverse(Node { row: neighbor.0, col: neighbor.1, distance: new_cost }));
+            }
+        }
+    }
+
+    // the first step on the route that needs the whole climb
+    let mut route = follow_predecessors(input.end, &predecessors);
+    route.reverse();
+    let needed = cost[input.end.0][input.end.1];
+    let step = route.windows(2)
+        .find(|step| climb(step[0], step[1]) == needed)
+        .unwrap();
+    Bottleneck { climb: needed, from: step[0], to: step[1] }
+}
+
+/// Who can get where, and what's in the way if S can't get to E
+pub fn reachability(input: &Data) -> ReachabilityReport {
+    let from_start = red_blob(input.start, &input.grid, can_climb);
+    let to_end = red_blob(input.end, &input.grid, can_climb_down);
+
+    let start_reaches_end = from_start[input.end.0][input.end.1] != usize::MAX;
+    let lowest_reaching_end = lowest_points(input).into_iter()
+        .filter(|&(row, col)| to_end[row][col] != usize::MAX)
+        .collect();
+    let bottleneck = if start_reaches_end {
+        None
+    }
+    else {
+        Some(find_bottleneck(input))
+    };
+
+    ReachabilityReport {
+        regions: regions(&input.grid),
+        start_reaches_end,
+        lowest_reaching_end,
+        bottleneck,
+    }
 }
 
 // Part 1: what is the fewest steps to get from Start to End?
+// (None if you can't get there at all)
 #[aoc(day12, part1)]
-pub fn solve_part1(input: &Data) -> usize {
+pub fn solve_part1(input: &Data) -> Option<usize> {
     solve_part1_inner(input)
 }
-fn solve_part1_inner(input: &Data) -> usize {
+fn solve_part1_inner(input: &Data) -> Option<usize> {
     // pass in is_valid_move as a closure: given the elevation grid, neighbor coords, and location coord,
     // is going from location to neighbor allowed?
-    let distances = red_blob(input.start, &input.grid, 
-        |grid, (row, col), location|{
-            grid[row][col] <= grid[location.0][location.1] + 1
-        }
-    );
+    let distances = red_blob(input.start, &input.grid, can_climb);
 
-    distances[input.end.0][input.end.1]
+    match distances[input.end.0][input.end.1] {
+        usize::MAX => None,
+        steps => Some(steps),
+    }
 }
 
 // Part 2: the fewest steps to move from any square with elevation a
 // to the end
+// (None if none of them can get there)
 #[aoc(day12, part2)]
-pub fn solve_part2(input: &Data) -> usize {
+pub fn solve_part2(input: &Data) -> Option<usize> {
     solve_part2_inner(input)
 }
-fn solve_part2_inner(input: &Data) -> usize {
+fn solve_part2_inner(input: &Data) -> Option<usize> {
     // pass in is_valid_move as a closure: given the elevation grid, neighbor coords, and location coord,
     // is going from neighbor to location allowed?
-    let distances = red_blob(input.end, &input.grid, 
-        |grid, (row, col), location|{
-            grid[location.0][location.1] <= grid[row][col] + 1 
-        }
-    );
-
-    let num_rows = input.grid.len();
-    let num_cols = input.grid[0].len();
-    let mut fewest_steps = usize::MAX;
-    for i in 0..num_rows {
-        for j in 0..num_cols {
-            if input.grid[i][j] == 0 && distances[i][j] < fewest_steps {
-                fewest_steps = distances[i][j]
-            }
-        }
-    }
-    fewest_steps
+    let distances = red_blob(input.end, &input.grid, can_climb_down);
+
+    lowest_points(input).into_iter()
+        .map(|(row, col)| distances[row][col])
+        .filter(|&steps| steps != usize::MAX)
+        .min()
 }
 
 #[cfg(test)]
@@ -191,7 +697,7 @@ abdefghi
         let input = super::input_generator(TEST_INPUT).unwrap();
         let result = super::solve_part1(&input);
 
-        assert_eq!(result, 31);
+        assert_eq!(result, Some(31));
     }
 
     #[test]
@@ -199,6 +705,147 @@ abdefghi
         let input = super::input_generator(TEST_INPUT).unwrap();
         let result = super::solve_part2(&input);
 
-        assert_eq!(result, 29);
+        assert_eq!(result, Some(29));
+    }
+
+    #[test]
+    fn test_climb_rules() {
+        use std::rc::Rc;
+        use super::{cheapest_route, fewest_steps, lowest_points, ClimbRules};
+
+        let input = super::input_generator(TEST_INPUT).unwrap();
+        let rules = ClimbRules::default();
+        assert_eq!(fewest_steps(&input, &[input.start()], &rules), Some(31));
+        assert_eq!(fewest_steps(&input, &lowest_points(&input), &rules), Some(29));
+        let distances = super::dijkstra(&[input.start()], input.grid(), &rules);
+        assert_eq!(distances[input.end().0][input.end().1], 31);
+        assert_eq!(format!("{:?}", rules),
+            "ClimbRules { max_ascent: 1, max_descent: None, diagonals: false, step_cost: <closure> }");
+
+        // cutting corners helps
+        let diagonal = ClimbRules { diagonals: true, ..ClimbRules::default() };
+        let steps = fewest_steps(&input, &[input.start], &diagonal).unwrap();
+        assert!(steps < 31);
+
+        // not allowed to go down at all
+        let no_descent = ClimbRules { max_descent: Some(0), ..ClimbRules::default() };
+        assert!(no_descent.allows(3, 4) && !no_descent.allows(4, 3) && !no_descent.allows(3, 5));
+
+        // climbing is expensive: the fewest steps still costs at least 25 for the climb
+        let expensive_climb = ClimbRules { step_cost: Rc::new(|change| if change > 0 { 10 } else { 1 }), ..ClimbRules::default() };
+        let cost = cheapest_route(&input, &[input.start], &expensive_climb).unwrap();
+        assert_eq!(cost, 25 * 10 + (31 - 25));
+        assert_eq!(fewest_steps(&input, &[input.start], &expensive_climb), Some(31));
+    }
+
+    #[test]
+    fn test_bad_input() {
+        let error = |input: &str| super::input_generator(input).unwrap_err().to_string();
+        assert_eq!(error("Sabqponm\nabcryxxl\naccs!xkk\n"), "line 3, column 5: '!' isn't an elevation, S or E");
+        assert_eq!(error("SabS\nabcE\n"), "line 1, column 4: second S (the first is at line 1, column 1)");
+        assert_eq!(error("SabE\nabcE\n"), "line 2, column 4: second E (the first is at line 1, column 4)");
+        assert_eq!(error("Sabc\nabcd\n"), "there's no E in the heightmap");
+        assert_eq!(error("aabc\nabcE\n"), "there's no S in the heightmap");
+        assert_eq!(error("SabE\nabc\n"), "line 2: 3 squares wide, but line 1 is 4");
+        assert_eq!(error(""), "the heightmap is empty");
+        assert_eq!(error("\nSE\n"), "line 1: no squares at all");
+    }
+
+    #[test]
+    fn test_reachability() {
+        let input = super::input_generator(TEST_INPUT).unwrap();
+        let report = super::reachability(&input);
+        assert!(report.start_reaches_end);
+        assert_eq!(report.bottleneck, None);
+        assert_eq!(report.lowest_reaching_end.len(), super::lowest_points(&input).len());
+        // every square is in exactly one region
+        assert_eq!(report.regions.iter().map(|region| region.len()).sum::<usize>(), 8 * 5);
+        assert!(report.regions.iter().any(|region| region.contains(&input.end)));
+
+        // a cliff right after S: only the a on the far side can climb up to E
+        let input = super::input_generator("SbzabcdefghijklmnopqrstuvwxyzE\n").unwrap();
+        assert_eq!(super::solve_part1(&input), None);
+        assert_eq!(super::solve_part2(&input), Some(26));
+        let report = super::reachability(&input);
+        assert!(!report.start_reaches_end);
+        assert_eq!(report.lowest_reaching_end, vec![(0, 3)]);
+        assert_eq!(report.bottleneck, Some(super::Bottleneck { climb: 24, from: (0, 1), to: (0, 2) }));
+        // the ramp up to E, S with its b, and the cliff on its own
+        assert_eq!(report.regions.iter().map(|region| region.len()).collect::<Vec<_>>(), vec![27, 2, 1]);
+    }
+
+    #[test]
+    fn test_pgm() {
+        // the example, one gray level per letter
+        let input = super::input_generator(TEST_INPUT).unwrap();
+        let mut plain = format!("P2\n# the example heightmap\n{} {}\n25\n", input.grid[0].len(), input.grid.len());
+        for row in &input.grid {
+            plain += &row.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(" ");
+            plain += "\n";
+        }
+        let from_plain = super::from_pgm(plain.as_bytes(), input.start, input.end, 26).unwrap();
+        assert_eq!(from_plain, input);
+        assert_eq!(super::solve_part1(&from_plain), Some(31));
+
+        // binary, with the values stretched over 0-250
+        let mut binary = format!("P5 {} {} 250\n", input.grid[0].len(), input.grid.len()).into_bytes();
+        binary.extend(input.grid.iter().flatten().map(|&e| (e * 10) as u8));
+        let from_binary = super::from_pgm(&binary, input.start, input.end, 26).unwrap();
+        assert_eq!(from_binary, input);
+
+        // every level gets its fair share of gray values, not just the top one
+        let spread = super::from_pgm(b"P2 3 1 255\n9 10 250", (0, 0), (0, 2), 26).unwrap();
+        assert_eq!(spread.grid, vec![vec![0, 1, 25]]);
+
+        assert!(super::from_pgm(b"P6 1 1 255\n\0\0\0", (0, 0), (0, 0), 26).is_err());
+        assert!(super::from_pgm(b"P5 2 2 255\n\0\0\0", (0, 0), (0, 0), 26).is_err());
+        assert!(super::from_pgm(b"P5 2 2 255", (0, 0), (0, 0), 26).is_err());
+        assert!(super::from_pgm(b"P2 18446744073709551615 2 255\n0 0", (0, 0), (0, 0), 26).is_err());
+        assert!(super::from_pgm(b"P5 18446744073709551615 2 255\n\0\0", (0, 0), (0, 0), 26).is_err());
+        assert!(super::from_pgm(b"P2 1 1 18446744073709551615\n0", (0, 0), (0, 0), 26).is_err());
+        assert!(super::from_pgm(b"P2 1 1 255\n7", (0, 0), (0, 0), usize::MAX).is_err());
+        assert!(super::from_pgm(b"P2 1 1 255\n0", (0, 0), (0, 1), 26).is_err());
+
+        let distances = super::distance_field(&input);
+        let image = super::distances_to_pgm(&distances);
+        let header = b"P5\n8 5\n255\n";
+        assert!(image.starts_with(header));
+        assert_eq!(image.len(), header.len() + 8 * 5);
+        let pixels = &image[header.len()..];
+        assert_eq!(pixels[0], 0);
+
+        // a wall nobody can climb shows up white
+        let walled = super::input_generator("SazE\n").unwrap();
+        let image = super::distances_to_pgm(&super::distance_field(&walled));
+        assert_eq!(&image[image.len() - 4..], &[0, 254, 255, 255]);
+    }
+
+    #[test]
+    fn test_routes() {
+        let input = super::input_generator(TEST_INPUT).unwrap();
+
+        let route = super::route_part1(&input).unwrap();
+        assert_eq!(route.len(), 31 + 1);
+        assert_eq!(route.first(), Some(&input.start));
+        assert_eq!(route.last(), Some(&input.end));
+        // not quite the puzzle's route, but just as short
+        assert_eq!(super::render_route(&input, &route),
+r#"v..v<<<<
+>v.vv<<^
+.v.v>E^^
+.>v>>>^^
+..>>>>>^
+"#);
+
+        let route = super::route_part2(&input).unwrap();
+        assert_eq!(route.len(), 29 + 1);
+        assert_eq!(input.grid[route[0].0][route[0].1], 0);
+        assert_eq!(route.last(), Some(&input.end));
+        // every step is one square, and never climbs more than 1
+        for step in route.windows(2) {
+            let (from, to) = (step[0], step[1]);
+            assert_eq!(from.0.abs_diff(to.0) + from.1.abs_diff(to.1), 1);
+            assert!(input.grid[to.0][to.1] <= input.grid[from.0][from.1] + 1);
+        }
     }
 }
\ No newline at end of file
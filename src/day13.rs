@@ -1,16 +1,80 @@
-use std::{cmp::Ordering, vec};
+use std::{cmp::Ordering, fmt, hash::{Hash, Hasher}, vec};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 /// "Packets" which consist of lists and integers
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// Equality and hashing follow the packet ordering below, so e.g. 2, [2] and [[2]]
+/// are all equal. Use structural_eq to tell them apart.
+#[derive(Debug, Clone)]
 pub enum Data {
     Number(usize),
     List(Vec<Data>)
 }
 
+impl Data {
+    /// The simplest packet that compares equal to this one
+    /// The ordering treats a number the same as a list holding just that number,
+    /// so unwrap those lists (from the inside out, so [[2]] becomes 2)
+    pub fn canonical(&self) -> Data {
+        match self {
+            Data::Number(n) => Data::Number(*n),
+            Data::List(list) => {
+                let list: Vec<Data> = list.iter().map(|d| d.canonical()).collect();
+                match list.as_slice() {
+                    [Data::Number(n)] => Data::Number(*n),
+                    _ => Data::List(list),
+                }
+            },
+        }
+    }
+
+    /// Are these exactly the same packet, written the same way?
+    pub fn structural_eq(&self, other: &Data) -> bool {
+        match (self, other) {
+            (Data::Number(me), Data::Number(them)) => me == them,
+            (Data::List(my_list), Data::List(their_list)) => {
+                my_list.len() == their_list.len()
+                    && my_list.iter().zip(their_list).all(|(mine, theirs)| mine.structural_eq(theirs))
+            },
+            _ => false,
+        }
+    }
+
+    /// Hash the structure as it is, without canonicalizing
+    fn hash_structure<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Data::Number(n) => {
+                0u8.hash(state);
+                n.hash(state);
+            },
+            Data::List(list) => {
+                1u8.hash(state);
+                list.len().hash(state);
+                for element in list {
+                    element.hash_structure(state);
+                }
+            },
+        }
+    }
+}
+
+impl PartialEq for Data {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Data {}
+
+impl Hash for Data {
+    // packets that compare equal have the same canonical form
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical().hash_structure(state);
+    }
+}
+
 impl Ord for Data {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         match (self, other) {
@@ -49,84 +113,542 @@ impl PartialOrd for Data {
     }
 }
 
-/// Parse the next data element for the packet
-/// Return the parsed element and the rest of the string
-fn parse_data(input: &str) -> (Data, &str) {
-    if input.starts_with("[") {
-        let mut list = vec![];
+/// How compare_explained made up its mind
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Decision {
+    // two different numbers
+    Numbers,
+    // the left list ran out of items first
+    LeftRanOut,
+    // the right list ran out of items first
+    RightRanOut,
+    // nothing differed: the packets are equal
+    Equal,
+}
 
-        let mut rest_of_str = &input[1..];
-        while !rest_of_str.is_empty() {
-            let b = rest_of_str.as_bytes()[0];
-            match b {
-                // finished the list: break out of the loop
-                b']' => {
-                    rest_of_str = &rest_of_str[1..];
-                    break;
-                },
-                // starting a new list
-                b'[' => {
-                    let (new_list, returned_str) = parse_data(rest_of_str);
-                    list.push(new_list);
-                    rest_of_str = returned_str;
+/// The result of compare_explained
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    pub ordering: Ordering,
+    pub decision: Decision,
+    // list indices from the top of the packets down to the deciding comparison
+    // (a promoted number counts as index 0 of its new list)
+    pub path: Vec<usize>,
+    // the two values at the deciding comparison
+    // (the two lists if one ran out, the whole packets if they're equal)
+    pub compared: (Data, Data),
+    // whether a number had to be promoted to a list on the way there
+    pub promoted: bool,
+    // the walkthrough, as (depth, text)
+    steps: Vec<(usize, String)>,
+}
+
+impl fmt::Display for Explanation {
+    /// The walkthrough, indented like the puzzle's
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (depth, text) in &self.steps {
+            writeln!(f, "{}- {}", "  ".repeat(*depth), text)?;
+        }
+        Ok(())
+    }
+}
+
+// the decision, path, compared values and whether there was a promotion
+type Decided = (Decision, Vec<usize>, (Data, Data), bool);
+
+/// Keeps track of where we are while explaining a comparison
+struct Explainer {
+    path: Vec<usize>,
+    // how many promotions we're currently inside
+    promotions: usize,
+    steps: Vec<(usize, String)>,
+    decided: Option<Decided>,
+}
+
+impl Explainer {
+    fn decide(&mut self, decision: Decision, compared: (&Data, &Data)) {
+        let compared = (compared.0.clone(), compared.1.clone());
+        self.decided = Some((decision, self.path.clone(), compared, self.promotions > 0));
+    }
+
+    // Same logic as Ord for Data, writing down every step
+    fn compare(&mut self, left: &Data, right: &Data, depth: usize) -> Ordering {
+        self.steps.push((depth, format!("Compare {} vs {}", left, right)));
+        match (left, right) {
+            (Data::Number(me), Data::Number(them)) => {
+                let ordering = me.cmp(them);
+                match ordering {
+                    Ordering::Less => self.steps.push((depth + 1,
+                        "Left side is smaller, so inputs are in the right order".to_string())),
+                    Ordering::Greater => self.steps.push((depth + 1,
+                        "Right side is smaller, so inputs are not in the right order".to_string())),
+                    Ordering::Equal => return ordering,
                 }
-                // comma: move on to the next thing
-                b',' => {
-                    rest_of_str = &rest_of_str[1..];
+                self.decide(Decision::Numbers, (left, right));
+                ordering
+            },
+            (Data::Number(me), Data::List(_)) => {
+                let promoted = Data::List(vec![Data::Number(*me)]);
+                self.steps.push((depth + 1,
+                    format!("Mixed types; convert left to {} and retry comparison", promoted)));
+                self.promotions += 1;
+                let ordering = self.compare(&promoted, right, depth + 1);
+                self.promotions -= 1;
+                ordering
+            },
+            (Data::List(_), Data::Number(them)) => {
+                let promoted = Data::List(vec![Data::Number(*them)]);
+                self.steps.push((depth + 1,
+                    format!("Mixed types; convert right to {} and retry comparison", promoted)));
+                self.promotions += 1;
+                let ordering = self.compare(left, &promoted, depth + 1);
+                self.promotions -= 1;
+                ordering
+            },
+            (Data::List(my_list), Data::List(their_list)) => {
+                for (i, (mine, theirs)) in my_list.iter().zip(their_list).enumerate() {
+                    self.path.push(i);
+                    let ordering = self.compare(mine, theirs, depth + 1);
+                    self.path.pop();
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                }
+                let ordering = my_list.len().cmp(&their_list.len());
+                match ordering {
+                    Ordering::Less => {
+                        self.steps.push((depth + 1,
+                            "Left side ran out of items, so inputs are in the right order".to_string()));
+                        self.decide(Decision::LeftRanOut, (left, right));
+                    },
+                    Ordering::Greater => {
+                        self.steps.push((depth + 1,
+                            "Right side ran out of items, so inputs are not in the right order".to_string()));
+                        self.decide(Decision::RightRanOut, (left, right));
+                    },
+                    Ordering::Equal => (),
+                }
+                ordering
+            },
+        }
+    }
+}
+
+/// Compare two packets like Ord does, but also say why
+/// Display the result to get a walkthrough like the puzzle's
+pub fn compare_explained(left: &Data, right: &Data) -> Explanation {
+    let mut explainer = Explainer { path: vec![], promotions: 0, steps: vec![], decided: None };
+    let ordering = explainer.compare(left, right, 0);
+
+    let (decision, path, compared, promoted) = match explainer.decided {
+        Some(decided) => decided,
+        None => {
+            explainer.steps.push((0, "Both sides are equal".to_string()));
+            (Decision::Equal, vec![], (left.clone(), right.clone()), false)
+        },
+    };
+    Explanation { ordering, decision, path, compared, promoted, steps: explainer.steps }
+}
+
+impl fmt::Display for Data {
+    /// The canonical form, like the puzzle input: [1,[2,3]]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Data::Number(n) => write!(f, "{}", n),
+            Data::List(list) => {
+                write!(f, "[")?;
+                for (i, element) in list.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            },
+        }
+    }
+}
+
+/// What went wrong parsing a packet
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ParseErrorKind {
+    // a [ that never gets closed
+    UnclosedList,
+    // a ] without a [ to go with it
+    UnmatchedClose,
+    // more stuff after the packet is finished
+    TrailingInput,
+    // nothing between two commas, or between a comma and a bracket
+    EmptyElement,
+    // digits that don't fit in a usize
+    InvalidNumber,
+    // something that isn't a bracket, comma, digit or whitespace
+    UnexpectedChar(char),
+    // the input ran out where we needed a value
+    UnexpectedEnd,
+}
+
+/// A packet parse error, with the byte offset where it happened
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ParseError {
+    pub offset: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ParseErrorKind::*;
+        let message = match self.kind {
+            UnclosedList => "list is never closed".to_string(),
+            UnmatchedClose => "unmatched ]".to_string(),
+            TrailingInput => "unexpected input after the packet".to_string(),
+            EmptyElement => "empty list element".to_string(),
+            InvalidNumber => "number too big".to_string(),
+            UnexpectedChar(c) => format!("unexpected {:?}", c),
+            UnexpectedEnd => "unexpected end of input".to_string(),
+        };
+        write!(f, "{} at byte {}", message, self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Recursive descent parser for a single packet
+struct Parser<'a> {
+    input: &'a str,
+    // byte offset of the next thing to look at
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, offset: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError { offset, kind }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b) if b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    /// A list or a number
+    fn parse_value(&mut self) -> std::result::Result<Data, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'[') => self.parse_list(),
+            Some(b'0'..=b'9') => self.parse_number(),
+            Some(b',') => Err(self.error(self.pos, ParseErrorKind::EmptyElement)),
+            Some(b']') => Err(self.error(self.pos, ParseErrorKind::UnmatchedClose)),
+            Some(_) => Err(self.unexpected_char()),
+            None => Err(self.error(self.pos, ParseErrorKind::UnexpectedEnd)),
+        }
+    }
+
+    fn parse_list(&mut self) -> std::result::Result<Data, ParseError> {
+        let open = self.pos;
+        self.pos += 1; // the [
+        let mut list = vec![];
+
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Data::List(list));
+        }
+        loop {
+            match self.peek() {
+                Some(b']') => return Err(self.error(self.pos, ParseErrorKind::EmptyElement)),
+                None => return Err(self.error(open, ParseErrorKind::UnclosedList)),
+                _ => list.push(self.parse_value()?),
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => {
+                    self.pos += 1;
+                    self.skip_whitespace();
                 },
-                // otherwise, it's a number
-                _ => {
-                    let (num, returned_str) = parse_data(rest_of_str);
-                    list.push(num);
-                    rest_of_str = returned_str;
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Data::List(list));
                 },
+                Some(_) => return Err(self.unexpected_char()),
+                None => return Err(self.error(open, ParseErrorKind::UnclosedList)),
             }
         }
-        
-        (Data::List(list), rest_of_str)
-    }
-    else {
-        // it's a number: we need to get the string up to the next "," or "]",
-        // and then parse it
-        let mut index = None;
-        for (i, b) in input.bytes().enumerate() {
-            if b == b']' || b == b',' {
-                index = Some(i);
-                break;
-            }
+    }
+
+    fn parse_number(&mut self) -> std::result::Result<Data, ParseError> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        self.input[start..self.pos].parse()
+            .map(Data::Number)
+            .map_err(|_| self.error(start, ParseErrorKind::InvalidNumber))
+    }
+
+    fn unexpected_char(&self) -> ParseError {
+        let c = self.input[self.pos..].chars().next().unwrap();
+        self.error(self.pos, ParseErrorKind::UnexpectedChar(c))
+    }
+}
+
+/// Parse a whole packet like [1,[2,3]]
+/// Whitespace is allowed anywhere between the parts
+pub fn parse_packet(input: &str) -> std::result::Result<Data, ParseError> {
+    let mut parser = Parser { input, pos: 0 };
+    let packet = parser.parse_value()?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(packet),
+        Some(b']') => Err(parser.error(parser.pos, ParseErrorKind::UnmatchedClose)),
+        Some(_) => Err(parser.error(parser.pos, ParseErrorKind::TrailingInput)),
+    }
+}
+
+
+/// Why a JSON value isn't a packet
+#[derive(Debug, PartialEq, Clone)]
+pub enum JsonError {
+    // packets only have lists and numbers
+    NotAPacket(String),
+    NegativeNumber(String),
+    FractionalNumber(f64),
+    TooBig(String),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonError::NotAPacket(value) => write!(f, "packets can only hold lists and numbers, not {}", value),
+            JsonError::NegativeNumber(n) => write!(f, "packets can't hold negative numbers like {}", n),
+            JsonError::FractionalNumber(n) => write!(f, "packets can only hold whole numbers, not {}", n),
+            JsonError::TooBig(n) => write!(f, "{} is too big for a packet", n),
         }
-        let index = index.unwrap_or(input.len());
-        let num: usize = input[..index].parse().unwrap();
-        (Data::Number(num), &input[index..])
     }
 }
 
+impl std::error::Error for JsonError {}
+
+impl TryFrom<&serde_json::Value> for Data {
+    type Error = JsonError;
+
+    fn try_from(value: &serde_json::Value) -> std::result::Result<Self, Self::Error> {
+        use serde_json::Value;
+        match value {
+            Value::Array(list) => Ok(Data::List(
+                list.iter().map(Data::try_from).collect::<std::result::Result<_, _>>()?)),
+            Value::Number(n) => {
+                if let Some(n) = n.as_u64() {
+                    usize::try_from(n).map(Data::Number).map_err(|_| JsonError::TooBig(n.to_string()))
+                }
+                else if let Some(n) = n.as_i64() {
+                    Err(JsonError::NegativeNumber(n.to_string()))
+                }
+                else {
+                    // anything else came in as a float: 1.0 and 1e2 are fine,
+                    // but so are numbers too big or too negative for an integer
+                    let f = n.as_f64().unwrap_or(f64::NAN);
+                    if f < 0.0 {
+                        Err(JsonError::NegativeNumber(f.to_string()))
+                    }
+                    else if f.fract() != 0.0 {
+                        Err(JsonError::FractionalNumber(f))
+                    }
+                    else if f >= usize::MAX as f64 {
+                        Err(JsonError::TooBig(f.to_string()))
+                    }
+                    else {
+                        Ok(Data::Number(f as usize))
+                    }
+                }
+            },
+            _ => Err(JsonError::NotAPacket(value.to_string())),
+        }
+    }
+}
+
+impl From<&Data> for serde_json::Value {
+    fn from(data: &Data) -> Self {
+        match data {
+            Data::Number(n) => serde_json::Value::from(*n),
+            Data::List(list) => serde_json::Value::Array(list.iter().map(serde_json::Value::from).collect()),
+        }
+    }
+}
+
+/// Read packet pairs from JSON Lines, one [left, right] pair per line
+/// Returns the packets in order, like input_generator
+pub fn input_generator_json(input: &str) -> Result<Vec<Data>> {
+    let mut packets = vec![];
+    for (i, line) in input.lines().enumerate() {
+        let line_number = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: serde_json::Value = serde_json::from_str(line)
+            .map_err(|e| anyhow!("line {}: {}", line_number, e))?;
+        match Data::try_from(&value).map_err(|e| anyhow!("line {}: {}", line_number, e))? {
+            Data::List(pair) if pair.len() == 2 => packets.extend(pair),
+            _ => return Err(anyhow!("line {}: expected a [left, right] pair", line_number)),
+        }
+    }
+    Ok(packets)
+}
+
+/// Write packets out as JSON Lines, one [left, right] pair per line
+/// (so there has to be an even number of them)
+pub fn to_json_lines(packets: &[Data]) -> Result<String> {
+    if !packets.len().is_multiple_of(2) {
+        return Err(anyhow!("{} packets can't be split into pairs", packets.len()));
+    }
+    Ok(packets.chunks(2)
+        .map(|pair| serde_json::Value::Array(pair.iter().map(serde_json::Value::from).collect()).to_string() + "\n")
+        .collect())
+}
+
+/// How big PacketGenerator's packets get
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketShape {
+    // how many lists deep (the outer list is depth 1)
+    pub max_depth: usize,
+    // the most elements in any one list
+    pub max_width: usize,
+    // numbers are between 0 and this
+    pub max_number: usize,
+}
+
+impl Default for PacketShape {
+    // about what the puzzle input looks like
+    fn default() -> Self {
+        PacketShape { max_depth: 5, max_width: 5, max_number: 10 }
+    }
+}
+
+/// Random packets, the same ones every time for the same seed
+/// (xorshift, so no dependencies)
+#[derive(Debug, Clone)]
+pub struct PacketGenerator {
+    state: u64,
+    shape: PacketShape,
+}
+
+impl PacketGenerator {
+    pub fn new(seed: u64, shape: PacketShape) -> Self {
+        // xorshift gets stuck on 0
+        PacketGenerator { state: seed ^ 0x9E37_79B9_7F4A_7C15, shape }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    // a number in [0, n]
+    fn up_to(&mut self, n: usize) -> usize {
+        match (n as u64).checked_add(1) {
+            Some(range) => (self.next_u64() % range) as usize,
+            // every u64 is in range already
+            None => self.next_u64() as usize,
+        }
+    }
+
+    /// A random number
+    pub fn number(&mut self) -> Data {
+        Data::Number(self.up_to(self.shape.max_number))
+    }
+
+    /// A random packet (always a list at the top, like the puzzle)
+    pub fn packet(&mut self) -> Data {
+        self.list(1)
+    }
+
+    fn list(&mut self, depth: usize) -> Data {
+        let width = self.up_to(self.shape.max_width);
+        let list = (0..width).map(|_| {
+            if depth < self.shape.max_depth && self.next_u64() & 1 == 0 {
+                self.list(depth + 1)
+            }
+            else {
+                self.number()
+            }
+        }).collect();
+        Data::List(list)
+    }
+}
+
+impl Iterator for PacketGenerator {
+    type Item = Data;
+
+    fn next(&mut self) -> Option<Data> {
+        Some(self.packet())
+    }
+}
 
 #[aoc_generator(day13)]
 pub fn input_generator(input: &str) -> Result<Vec<Data>> {
     input_generator_inner(input)
 }
 fn input_generator_inner(input: &str) -> Result<Vec<Data>> {
-    //let mut pairs = vec![];
     let mut packets = vec![];
+    for (left, right) in parse_pairs(input)? {
+        packets.push(left);
+        packets.push(right)
+    }
+    Ok(packets)
+}
 
-    let v: Vec<&str> = input.lines().collect();
+/// Runs of non-blank lines, with their 1-indexed line numbers
+fn line_groups(input: &str) -> Vec<Vec<(usize, &str)>> {
+    let mut groups = vec![];
+    let mut group = vec![];
+    for (i, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            if !group.is_empty() {
+                groups.push(std::mem::take(&mut group));
+            }
+        }
+        else {
+            group.push((i + 1, line));
+        }
+    }
+    if !group.is_empty() {
+        groups.push(group);
+    }
+    groups
+}
 
-    // Input is pairs of "packets", separated by blank lines
-    for pair in v.chunks(3) {
-        let (left, left_remainder) = parse_data(pair[0]);
-        let (right, right_remainder) = parse_data(pair[1]);
-        
-        assert!(left_remainder.is_empty());
-        assert!(right_remainder.is_empty());
+fn parse_line(line_number: usize, line: &str) -> Result<Data> {
+    parse_packet(line).map_err(|e| anyhow!("line {}: {}", line_number, e))
+}
 
-        //pairs.push((left,right));
-        packets.push(left);
-        packets.push(right)
+/// Input is pairs of "packets", separated by blank lines
+/// (any number of them). Every group has to be exactly two packets.
+pub fn parse_pairs(input: &str) -> Result<Vec<(Data, Data)>> {
+    let mut pairs = vec![];
+    for group in line_groups(input) {
+        match group.as_slice() {
+            [(left_line, left), (right_line, right)] => {
+                pairs.push((parse_line(*left_line, left)?, parse_line(*right_line, right)?));
+            },
+            _ => return Err(anyhow!("line {}: expected a pair of packets, found {} packet(s)",
+                group[0].0, group.len())),
+        }
     }
+    Ok(pairs)
+}
 
-    //Ok(pairs)
-    Ok(packets)
+/// Every non-blank line is a packet, pairs or not (all part 2 cares about)
+pub fn parse_packet_list(input: &str) -> Result<Vec<Data>> {
+    line_groups(input).into_iter()
+        .flatten()
+        .map(|(line_number, line)| parse_line(line_number, line))
+        .collect()
 }
 
 
@@ -161,27 +683,29 @@ fn solve_part2_inner(input: &[Data]) -> usize {
     let two = List(vec![List(vec![Number(2)])]);
     let six = List(vec![List(vec![Number(6)])]);
 
-    let mut packets = input.to_vec();
-    packets.push(two);
-    packets.push(six);
-    packets.sort(); // since Ord is conveniently implemented
-
-    // push drains, so declare them again?
-    let two = List(vec![List(vec![Number(2)])]);
-    let six = List(vec![List(vec![Number(6)])]);
+    decoder_key(input, &[two, six])
+}
 
-    let mut two_index = None;
-    let mut six_index = None;
-    for (i, data) in packets.iter().enumerate() {
-        if data.eq(&two) {
-            two_index = Some(i+1);
-        }
-        else if data.eq(&six) {
-            six_index = Some(i+1);
-        }
-    }
+/// Where each divider would end up (1-indexed) if the packets and dividers were sorted,
+/// without sorting: it's 1 + however many things are smaller than it
+/// Dividers that are equal to each other keep their order
+pub fn divider_positions(packets: &[Data], dividers: &[Data]) -> Vec<usize> {
+    dividers.iter().enumerate().map(|(i, divider)| {
+        let smaller_packets = packets.iter().filter(|&packet| packet < divider).count();
+        let smaller_dividers = dividers.iter().enumerate()
+            .filter(|&(j, other)| match other.cmp(divider) {
+                Ordering::Less => true,
+                Ordering::Equal => j < i,
+                Ordering::Greater => false,
+            })
+            .count();
+        1 + smaller_packets + smaller_dividers
+    }).collect()
+}
 
-    two_index.unwrap() * six_index.unwrap()
+/// The product of the positions of the dividers
+pub fn decoder_key(packets: &[Data], dividers: &[Data]) -> usize {
+    divider_positions(packets, dividers).iter().product()
 }
 
 #[cfg(test)]
@@ -227,4 +751,273 @@ r#"[1,1,3,1,1]
         assert_eq!(result, 140);
     }
 
+    #[test]
+    fn test_parse_errors() {
+        use super::{parse_packet, ParseError, ParseErrorKind::*};
+
+        let error = |offset, kind| Err(ParseError { offset, kind });
+        assert_eq!(parse_packet("[1,[2,3]"), error(0, UnclosedList));
+        assert_eq!(parse_packet("[1,[2,3"), error(3, UnclosedList));
+        assert_eq!(parse_packet("[1,2]]"), error(5, UnmatchedClose));
+        assert_eq!(parse_packet("]"), error(0, UnmatchedClose));
+        assert_eq!(parse_packet("[1,2] [3]"), error(6, TrailingInput));
+        assert_eq!(parse_packet("[1,,2]"), error(3, EmptyElement));
+        assert_eq!(parse_packet("[1,2,]"), error(5, EmptyElement));
+        assert_eq!(parse_packet("[,]"), error(1, EmptyElement));
+        assert_eq!(parse_packet("[1,a]"), error(3, UnexpectedChar('a')));
+        assert_eq!(parse_packet("[1 2]"), error(3, UnexpectedChar('2')));
+        assert_eq!(parse_packet("[99999999999999999999999]"), error(1, InvalidNumber));
+        assert_eq!(parse_packet("  "), error(2, UnexpectedEnd));
+
+        assert_eq!(parse_packet(" [ 1 ,\t[2, 3 ] ] "), parse_packet("[1,[2,3]]"));
+        assert!(super::input_generator("[1,2]\n[1,,2]\n").is_err());
+    }
+
+    #[test]
+    fn test_divider_positions() {
+        use super::parse_packet;
+
+        let input = super::input_generator(TEST_INPUT).unwrap();
+        let dividers: Vec<_> = ["[[2]]", "[[6]]"].iter().map(|p| parse_packet(p).unwrap()).collect();
+        assert_eq!(super::divider_positions(&input, &dividers), vec![10, 14]);
+        assert_eq!(super::decoder_key(&input, &dividers), 140);
+
+        // same answer as actually sorting, for any dividers
+        let dividers: Vec<_> = ["[]", "[[2]]", "[5]", "[1,1,4]", "[[2]]", "[[[[9]]]]"]
+            .iter().map(|p| parse_packet(p).unwrap()).collect();
+        let mut sorted: Vec<_> = input.iter().chain(&dividers).cloned().collect();
+        sorted.sort();
+        let positions = super::divider_positions(&input, &dividers);
+        for (divider, position) in dividers.iter().zip(&positions) {
+            assert_eq!(&sorted[position - 1], divider);
+        }
+        // equal dividers get different positions
+        assert_eq!(positions[4], positions[1] + 1);
+    }
+
+    #[test]
+    fn test_pair_grouping() {
+        let spaced = TEST_INPUT.replace("\n\n", "\n\n\n") + "\n\n";
+        let input = super::input_generator(&spaced).unwrap();
+        assert_eq!(input.len(), 16);
+        assert_eq!(super::solve_part1(&input), 13);
+
+        let error = super::input_generator("[1]\n[2]\n\n[3]\n[4]\n[5]\n\n[6]\n[7]\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 4: expected a pair of packets, found 3 packet(s)");
+        let error = super::input_generator("[1]\n[2]\n\n[3]").unwrap_err();
+        assert_eq!(error.to_string(), "line 4: expected a pair of packets, found 1 packet(s)");
+        let error = super::input_generator("[1]\n[2]\n\n[3]\n[[4]\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 5: list is never closed at byte 0");
+
+        // part 2 doesn't care about pairs
+        let packets = super::parse_packet_list("[1]\n[2]\n\n[3]\n[4]\n[5]\n\n\n[6]").unwrap();
+        assert_eq!(packets.len(), 6);
+        assert_eq!(super::solve_part2(&super::parse_packet_list(TEST_INPUT).unwrap()), 140);
+    }
+
+    #[test]
+    fn test_json() {
+        use super::{Data, JsonError};
+        use serde_json::json;
+
+        let input = super::input_generator(TEST_INPUT).unwrap();
+        let json_lines = super::to_json_lines(&input).unwrap();
+        assert_eq!(json_lines.lines().next(), Some("[[1,1,3,1,1],[1,1,5,1,1]]"));
+        let from_json = super::input_generator_json(&json_lines).unwrap();
+        assert_eq!(from_json.len(), input.len());
+        assert!(from_json.iter().zip(&input).all(|(a, b)| a.structural_eq(b)));
+        assert_eq!(super::solve_part1(&from_json), 13);
+
+        assert!(Data::try_from(&json!([1, [2, []]])).unwrap().structural_eq(&super::parse_packet("[1,[2,[]]]").unwrap()));
+        assert_eq!(Data::try_from(&json!([1, "2"])), Err(JsonError::NotAPacket("\"2\"".to_string())));
+        assert_eq!(Data::try_from(&json!({"a": 1})), Err(JsonError::NotAPacket("{\"a\":1}".to_string())));
+        assert_eq!(Data::try_from(&json!([-3])), Err(JsonError::NegativeNumber("-3".to_string())));
+        assert_eq!(Data::try_from(&json!([1.5])), Err(JsonError::FractionalNumber(1.5)));
+        let too_big: serde_json::Value = serde_json::from_str("[100000000000000000000]").unwrap();
+        assert_eq!(Data::try_from(&too_big), Err(JsonError::TooBig("100000000000000000000".to_string())));
+        let floats: serde_json::Value = serde_json::from_str("[1.0, 1e2, 0.0]").unwrap();
+        assert!(Data::try_from(&floats).unwrap().structural_eq(&super::parse_packet("[1,100,0]").unwrap()));
+        let too_negative: serde_json::Value = serde_json::from_str("[-100000000000000000000]").unwrap();
+        assert_eq!(Data::try_from(&too_negative),
+            Err(JsonError::NegativeNumber("-100000000000000000000".to_string())));
+        assert_eq!(Data::try_from(&json!([-0.5])), Err(JsonError::NegativeNumber("-0.5".to_string())));
+        assert!(super::to_json_lines(&input[..3]).is_err());
+
+        let error = super::input_generator_json("[[1],[2]]\n[[1],[2],[3]]\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: expected a [left, right] pair");
+        assert!(super::input_generator_json("[[1],[null]]\n").is_err());
+    }
+
+    #[test]
+    fn test_compare_explained() {
+        use super::{compare_explained, Decision};
+
+        let input = super::input_generator(TEST_INPUT).unwrap();
+        for pair in input.chunks(2) {
+            let explanation = compare_explained(&pair[0], &pair[1]);
+            assert_eq!(explanation.ordering, pair[0].cmp(&pair[1]));
+        }
+
+        let explanation = compare_explained(&input[2], &input[3]);
+        assert_eq!(explanation.to_string(),
+r#"- Compare [[1],[2,3,4]] vs [[1],4]
+  - Compare [1] vs [1]
+    - Compare 1 vs 1
+  - Compare [2,3,4] vs 4
+    - Mixed types; convert right to [4] and retry comparison
+    - Compare [2,3,4] vs [4]
+      - Compare 2 vs 4
+        - Left side is smaller, so inputs are in the right order
+"#);
+        assert_eq!(explanation.decision, Decision::Numbers);
+        assert_eq!(explanation.path, vec![1, 0]);
+        assert_eq!(explanation.compared.0.to_string(), "2");
+        assert_eq!(explanation.compared.1.to_string(), "4");
+        assert!(explanation.promoted);
+
+        let explanation = compare_explained(&input[6], &input[7]);
+        assert_eq!(explanation.to_string(),
+r#"- Compare [[4,4],4,4] vs [[4,4],4,4,4]
+  - Compare [4,4] vs [4,4]
+    - Compare 4 vs 4
+    - Compare 4 vs 4
+  - Compare 4 vs 4
+  - Compare 4 vs 4
+  - Left side ran out of items, so inputs are in the right order
+"#);
+        assert_eq!(explanation.decision, Decision::LeftRanOut);
+        assert!(explanation.path.is_empty());
+        assert!(!explanation.promoted);
+
+        let explanation = compare_explained(&input[12], &input[13]);
+        assert_eq!(explanation.decision, Decision::RightRanOut);
+        assert_eq!(explanation.path, vec![0]);
+
+        let explanation = compare_explained(&input[0], &input[0]);
+        assert_eq!(explanation.decision, Decision::Equal);
+        assert!(explanation.to_string().ends_with("- Both sides are equal\n"));
+    }
+
+    #[test]
+    fn test_total_order_properties() {
+        use std::cmp::Ordering;
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+        use super::{Data, PacketGenerator, PacketShape};
+
+        let hash = |data: &Data| {
+            let mut hasher = DefaultHasher::new();
+            data.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        // small numbers and shapes so plenty of packets end up equal
+        let shape = PacketShape { max_depth: 3, max_width: 3, max_number: 3 };
+        let mut generator = PacketGenerator::new(2022, shape);
+        let packets: Vec<Data> = generator.by_ref().take(1500).collect();
+        assert!(PacketGenerator::new(2022, shape).take(1500).zip(&packets).all(|(a, b)| a.structural_eq(b)));
+
+        // the whole range of numbers works too
+        let mut huge = PacketGenerator::new(2022, PacketShape { max_number: usize::MAX, ..PacketShape::default() });
+        huge.packet();
+        assert!((0..100).any(|_| matches!(huge.number(), Data::Number(n) if n > u32::MAX as usize)));
+
+        // every pair of the 1500 packets (about 2.25 million comparisons)
+        let mut num_equal = 0;
+        for a in &packets {
+            for b in &packets {
+                let ordering = a.cmp(b);
+                // antisymmetry
+                assert_eq!(b.cmp(a), ordering.reverse(), "{} vs {}", a, b);
+                // Eq, Hash and the canonical form agree with Ord
+                assert_eq!(a == b, ordering == Ordering::Equal, "{} vs {}", a, b);
+                assert_eq!(a.canonical().structural_eq(&b.canonical()), ordering == Ordering::Equal);
+                if ordering == Ordering::Equal {
+                    assert_eq!(hash(a), hash(b), "{} vs {}", a, b);
+                    num_equal += 1;
+                }
+            }
+        }
+        assert!(num_equal > packets.len());
+
+        // transitivity, on 100k random triples, trying every order of each one
+        let mut num_chains = 0;
+        for _ in 0..100_000 {
+            let triple = [(); 3].map(|_| &packets[generator.up_to(packets.len() - 1)]);
+            for (a, b, c) in [(0, 1, 2), (0, 2, 1), (1, 0, 2), (1, 2, 0), (2, 0, 1), (2, 1, 0)] {
+                let (a, b, c) = (triple[a], triple[b], triple[c]);
+                if a <= b && b <= c {
+                    assert!(a <= c, "{} <= {} <= {}", a, b, c);
+                    num_chains += 1;
+                }
+            }
+        }
+        // every triple has at least one order that's a chain
+        assert!(num_chains >= 100_000);
+
+        // a number compares exactly like the list holding just that number
+        for packet in &packets {
+            let n = generator.number();
+            let promoted = Data::List(vec![n.clone()]);
+            assert_eq!(n.cmp(packet), promoted.cmp(packet), "{} vs {}", n, packet);
+            assert_eq!(packet.cmp(&n), packet.cmp(&promoted), "{} vs {}", packet, n);
+        }
+
+        // and sorting leaves everything in order
+        let mut sorted = packets.clone();
+        sorted.sort();
+        assert!(sorted.windows(2).all(|w| w[0] <= w[1]));
+
+        // bigger packets still round trip through Display
+        for packet in PacketGenerator::new(7, PacketShape::default()).take(1000) {
+            assert!(super::parse_packet(&packet.to_string()).unwrap().structural_eq(&packet));
+        }
+    }
+
+    #[test]
+    fn test_eq_matches_ord() {
+        use std::collections::{BTreeSet, HashSet};
+        use super::parse_packet;
+
+        let two = parse_packet("2").unwrap();
+        let wrapped = parse_packet("[[2]]").unwrap();
+        assert_eq!(two, wrapped);
+        assert_eq!(two.cmp(&wrapped), std::cmp::Ordering::Equal);
+        assert!(!two.structural_eq(&wrapped));
+        assert!(wrapped.canonical().structural_eq(&two));
+
+        let packets: Vec<_> = ["2", "[2]", "[[2]]", "[[2],[3]]", "[2,3]", "[2,[3]]", "[[]]", "[]"]
+            .iter().map(|p| parse_packet(p).unwrap()).collect();
+        assert_eq!(packets.iter().cloned().collect::<HashSet<_>>().len(), 4);
+        assert_eq!(packets.iter().cloned().collect::<BTreeSet<_>>().len(), 4);
+        assert_eq!(parse_packet("[[2],[[3]]]").unwrap().canonical().to_string(), "[2,3]");
+        assert_eq!(parse_packet("[[[]]]").unwrap().canonical().to_string(), "[[[]]]");
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        use super::Data::{self, *};
+
+        // every packet up to 2 levels deep with up to 2 elements per list
+        let mut packets: Vec<Data> = (0..3).map(Number).chain([Number(10), Number(12345)]).collect();
+        for _ in 0..2 {
+            let mut lists = vec![List(vec![])];
+            for a in &packets {
+                lists.push(List(vec![a.clone()]));
+                for b in &packets {
+                    lists.push(List(vec![a.clone(), b.clone()]));
+                }
+            }
+            packets.extend(lists);
+        }
+        assert!(packets.len() > 1000);
+
+        for packet in packets {
+            let displayed = packet.to_string();
+            assert!(super::parse_packet(&displayed).unwrap().structural_eq(&packet), "{}", displayed);
+        }
+        assert_eq!(List(vec![Number(1), List(vec![Number(2), Number(3)]), List(vec![])]).to_string(),
+            "[1,[2,3],[]]");
+    }
+
 }
\ No newline at end of file
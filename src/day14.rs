@@ -1,24 +1,168 @@
-use std::cmp::{min, max};
+use std::{cmp::{min, max}, collections::HashMap, ops::RangeInclusive};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Material {
     Air,
     Rock,
-    Sand
+    Sand,
+    // water that has filled a basin and stays put
+    StillWater,
+    // water that is passing through on its way somewhere else
+    FlowingWater,
 }
 
+// Coordinates are (row, col), i.e. (y, x)
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum SandAction {
-    Fall((usize, usize)),
-    AtRest((usize, usize)),
+    Fall((isize, isize)),
+    AtRest((isize, isize)),
     Abyss,
 }
 
-type Data = Vec<Vec<Material>>;
+/// The cave, stored sparsely so any coordinates work
+/// (including negative ones, and however wide the sand pile gets in part 2)
+/// Coordinates are (row, col), i.e. (y, x)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Cave {
+    // everything that isn't air
+    cells: HashMap<(isize, isize), Material>,
+    // the lowest rock: without a floor, anything below this falls forever
+    lowest_rock: isize,
+    // the row of the infinite floor, if there is one
+    floor: Option<isize>,
+}
+
+impl Cave {
+    pub fn new() -> Self {
+        Cave { cells: HashMap::new(), lowest_rock: isize::MIN, floor: None }
+    }
+
+    pub fn get(&self, coord: (isize, isize)) -> Material {
+        if Some(coord.0) == self.floor {
+            return Material::Rock;
+        }
+        *self.cells.get(&coord).unwrap_or(&Material::Air)
+    }
+
+    pub fn set(&mut self, coord: (isize, isize), material: Material) {
+        if material == Material::Rock {
+            self.lowest_rock = max(self.lowest_rock, coord.0);
+        }
+        if material == Material::Air {
+            self.cells.remove(&coord);
+        }
+        else {
+            self.cells.insert(coord, material);
+        }
+    }
+
+    pub fn lowest_rock(&self) -> isize {
+        self.lowest_rock
+    }
+
+    pub fn floor(&self) -> Option<isize> {
+        self.floor
+    }
+
+    /// The same cave with an infinitely wide floor two rows below the lowest rock
+    /// (or below the sand source, if all the rock is above it)
+    pub fn with_floor(&self) -> Cave {
+        let mut cave = self.clone();
+        cave.floor = Some(max(self.lowest_rock, SAND_SOURCE.0) + 2);
+        cave
+    }
+
+    /// Would sand here fall forever? (never with a floor)
+    pub fn is_below_everything(&self, coord: (isize, isize)) -> bool {
+        self.floor.is_none() && coord.0 >= self.lowest_rock
+    }
+
+    /// How many cells hold this material (not counting the floor)
+    pub fn count(&self, material: Material) -> usize {
+        self.cells.values().filter(|&&m| m == material).count()
+    }
+
+    /// Smallest (rows, cols) that contain everything that isn't air
+    pub fn bounds(&self) -> Option<(RangeInclusive<isize>, RangeInclusive<isize>)> {
+        let rows = self.cells.keys().map(|&(row, _)| row);
+        let cols = self.cells.keys().map(|&(_, col)| col);
+        Some((rows.clone().min()?..=rows.max()?, cols.clone().min()?..=cols.max()?))
+    }
+
+    /// What to draw: everything that isn't air, plus the sand source and the floor
+    fn render_bounds(&self) -> (RangeInclusive<isize>, RangeInclusive<isize>) {
+        let (source_row, source_col) = SAND_SOURCE;
+        let (rows, cols) = self.bounds()
+            .unwrap_or((source_row..=source_row, source_col..=source_col));
+        let row_min = min(*rows.start(), source_row);
+        let row_max = max(*rows.end(), self.floor.unwrap_or(source_row));
+        let col_min = min(*cols.start(), source_col);
+        let col_max = max(*cols.end(), source_col);
+        (row_min..=row_max, col_min..=col_max)
+    }
+
+    /// Draw the cave like the puzzle does:
+    /// # for rock, o for sand, . for air and + for the sand source,
+    /// plus ~ for still water and | for flowing water,
+    /// cropped to the part of the cave that has anything in it
+    pub fn render(&self) -> String {
+        let (rows, cols) = self.render_bounds();
+        let mut rendered = String::new();
+        for row in rows {
+            for col in cols.clone() {
+                let c = match self.get((row, col)) {
+                    Material::Rock => '#',
+                    Material::Sand => 'o',
+                    Material::StillWater => '~',
+                    Material::FlowingWater => '|',
+                    Material::Air if (row, col) == SAND_SOURCE => '+',
+                    Material::Air => '.',
+                };
+                rendered.push(c);
+            }
+            rendered.push('\n');
+        }
+        rendered
+    }
+
+    /// The same picture as render, as a binary PPM image with one pixel per cell
+    /// (big inputs are hundreds of cells wide, too much to read as text)
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let (rows, cols) = self.render_bounds();
+        let width = cols.end() - cols.start() + 1;
+        let height = rows.end() - rows.start() + 1;
+        let mut image = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+        for row in rows {
+            for col in cols.clone() {
+                let pixel = match self.get((row, col)) {
+                    Material::Rock => [110, 110, 110],
+                    Material::Sand => [230, 200, 120],
+                    Material::StillWater => [40, 80, 200],
+                    Material::FlowingWater => [120, 170, 240],
+                    Material::Air if (row, col) == SAND_SOURCE => [220, 40, 40],
+                    Material::Air => [20, 20, 30],
+                };
+                image.extend(pixel);
+            }
+        }
+        image
+    }
+}
+
+impl Default for Cave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type Data = Cave;
+
+// Sand comes from the point x=500 y=0
+const SAND_SOURCE: (isize, isize) = (0, 500);
 
 // Each line is a rock structure
 // A -> B -> C
@@ -30,126 +174,283 @@ pub fn input_generator(input: &str) -> Result<Data> {
 }
 fn input_generator_inner(input: &str) -> Result<Data> {
     use Material::*;
-    // first pass: read all the lines and put them in a more convenient format
-    let mut rocks = vec![];
-    // min/max x and y for sizing the grid?
-    let mut xmin = 600;
-    let mut xmax = 0;
-    let mut ymin = 600;
-    let mut ymax = 0;
+    let mut cave = Cave::new();
     // Coordinates: x goes right and y goes down
     for line in input.lines() {
         if line.is_empty() {
             continue;
         }
-        let mut rock_structure = vec![];
+        let mut prev_coord = None;
         for coord in line.split(" -> ") {
             let (x,y) = coord.split_once(",").unwrap();
-            let x: usize = x.parse()?;
-            let y: usize = y.parse()?;
-            rock_structure.push((x,y));
-
-            xmin = min(xmin, x);
-            xmax = max(xmax, x);
-            ymin = min(ymin, y);
-            ymax = max(ymax, y);
-        }
-        rocks.push(rock_structure);
-    }
-
-    // could probably use x/ymin to make the grid smaller i guess
-    // Note that the grid is [y][x]: row then col
-    // the coords are 0-indexed this time
-    let mut grid = vec![vec![Air; xmax+1]; ymax+1];
-
-    for rock_structure in rocks {
-        let mut prev_coord = None;
-        for (x, y) in rock_structure {
+            let x: isize = x.parse()?;
+            let y: isize = y.parse()?;
             match prev_coord {
                 Some((prev_x, prev_y)) => {
                     if prev_x == x {// vertical line
                         let y_lower = min(prev_y, y);
                         let y_upper = max(prev_y, y);
                         for row in y_lower..=y_upper {
-                            grid[row][x] = Rock
+                            cave.set((row, x), Rock)
                         }
                     }
                     else {// horizontal line
                         let x_lower = min(prev_x, x);
                         let x_upper = max(prev_x, x);
                         for col in x_lower..=x_upper {
-                            grid[y][col] = Rock
+                            cave.set((y, col), Rock)
                         }
                     }
-                    prev_coord = Some((x,y));
                 },
-                None => prev_coord = Some((x,y)),
+                None => cave.set((y, x), Rock),
             }
+            prev_coord = Some((x,y));
+        }
+    }
+    Ok(cave)
+}
+
+// The usual rule: down, then down and to the left, then down and to the right
+// as (row, col) offsets
+const DEFAULT_FALL: [(isize, isize); 3] = [(1, 0), (1, -1), (1, 1)];
+
+/// Where sand tries to go next, in order of preference, as (row, col) offsets
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FallRules {
+    directions: Vec<(isize, isize)>,
+}
+
+impl FallRules {
+    /// Every direction has to go down at least one row,
+    /// otherwise sand could wander back and forth forever
+    pub fn new(directions: Vec<(isize, isize)>) -> Result<Self> {
+        if let Some(direction) = directions.iter().find(|(d_row, _)| *d_row < 1) {
+            return Err(anyhow!("sand has to fall: {:?} doesn't go down", direction));
         }
+        Ok(FallRules { directions })
     }
-    Ok(grid)
+
+    pub fn directions(&self) -> &[(isize, isize)] {
+        &self.directions
+    }
+}
+
+impl Default for FallRules {
+    fn default() -> Self {
+        FallRules { directions: DEFAULT_FALL.to_vec() }
+    }
+}
+
+fn sand_step(coord: (isize, isize), cave: &Cave) -> SandAction {
+    sand_step_with(coord, cave, &DEFAULT_FALL)
 }
 
-fn sand_step(coord: (usize, usize), grid: &Data) -> SandAction {
+fn sand_step_with(coord: (isize, isize), cave: &Cave, directions: &[(isize, isize)]) -> SandAction {
     let (row, col) = coord;
     // Basic check: there shouldn't be anything already here
-    assert!(grid[row][col] == Material::Air);
+    assert!(cave.get(coord) == Material::Air);
 
     // Bounds check: have we fallen into the abyss?
-    if row > grid.len() - 2 || col > grid[0].len() - 2 {
+    if cave.is_below_everything(coord) {
         return SandAction::Abyss
     }
 
-    // Can it fall down?
-    if Material::Air == grid[row+1][col]{
-        return SandAction::Fall((row+1, col))
-    }
-    // Can it fall one step down and to the left?
-    if Material::Air == grid[row+1][col-1]{
-        return SandAction::Fall((row+1, col-1))
-    }
-    // Can it fall one step down and to the right?
-    if Material::Air == grid[row+1][col+1]{
-        return SandAction::Fall((row+1, col+1))
+    // Can it fall in any of the directions? Try them in order
+    for &(d_row, d_col) in directions {
+        // off the edge of the coordinates counts as blocked
+        let next = match (row.checked_add(d_row), col.checked_add(d_col)) {
+            (Some(next_row), Some(next_col)) => (next_row, next_col),
+            _ => continue,
+        };
+        if Material::Air == cave.get(next) {
+            return SandAction::Fall(next)
+        }
     }
     
     // Else it cannot move and comes to rest
     SandAction::AtRest(coord)
 }
 
-// Part 1: units of sand until sand falls into the abyss
-#[aoc(day14, part1)]
-pub fn solve_part1(input: &Data) -> usize {
-    solve_part1_inner(input)
+/// Everything that can happen to a unit of sand, in order
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SandEvent {
+    // a new unit of sand appeared at the source
+    Spawned((isize, isize)),
+    // it fell one step, to here
+    FellTo((isize, isize)),
+    // it came to rest here
+    CameToRest((isize, isize)),
+    // it fell past the lowest rock from here, and will keep falling forever
+    FellIntoAbyss((isize, isize)),
+    // there's sand sitting on this source, so nothing more can come out of it
+    SourceBlocked((isize, isize)),
 }
-fn solve_part1_inner(input: &Data) -> usize {
-    let mut grid = input.clone();
-    let mut num_sand = 0;
-    // Sand comes from the point x=500 y=0
-    let mut sand_coord = (0, 500);
-    loop {
-        //dbg!(num_sand, sand_coord);
-        match sand_step(sand_coord, &grid) {
+
+/// When a SandSimulation should stop
+/// (it always stops once every source is blocked or pouring into the abyss)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StopCondition {
+    // the first time sand falls into the abyss (part 1)
+    Abyss,
+    // once this many units of sand have come to rest
+    Grains(usize),
+}
+
+/// Drops sand into a cave one step at a time
+/// With more than one source, they take turns dropping a unit of sand
+/// Iterate over it to get a SandEvent for every step
+pub struct SandSimulation {
+    cave: Cave,
+    sources: Vec<(isize, isize)>,
+    // whose turn it is
+    next_source: usize,
+    // sources that are done: blocked, or their sand falls into the abyss
+    // (which it would keep doing forever)
+    blocked: Vec<bool>,
+    fall_rules: FallRules,
+    stop_conditions: Vec<StopCondition>,
+    // the unit of sand that's currently falling, and which source it came from
+    falling: Option<(isize, isize)>,
+    falling_from: usize,
+    num_resting: usize,
+    finished: bool,
+}
+
+impl SandSimulation {
+    /// Sand from the usual source, stopping when it falls into the abyss
+    pub fn new(cave: Cave) -> Self {
+        SandSimulation {
+            cave,
+            sources: vec![SAND_SOURCE],
+            next_source: 0,
+            blocked: vec![false],
+            fall_rules: FallRules::default(),
+            stop_conditions: vec![StopCondition::Abyss],
+            falling: None,
+            falling_from: 0,
+            num_resting: 0,
+            finished: false,
+        }
+    }
+
+    pub fn with_source(self, source: (isize, isize)) -> Self {
+        self.with_sources(vec![source])
+    }
+
+    /// Several sources, taking turns in this order
+    pub fn with_sources(mut self, sources: Vec<(isize, isize)>) -> Self {
+        self.blocked = vec![false; sources.len()];
+        self.sources = sources;
+        self.next_source = 0;
+        self
+    }
+
+    pub fn with_fall_rules(mut self, fall_rules: FallRules) -> Self {
+        self.fall_rules = fall_rules;
+        self
+    }
+
+    /// Replace the stop conditions (no conditions: run until every source is blocked,
+    /// or its sand falls into the abyss)
+    pub fn with_stop_conditions(mut self, stop_conditions: Vec<StopCondition>) -> Self {
+        self.stop_conditions = stop_conditions;
+        self
+    }
+
+    pub fn cave(&self) -> &Cave {
+        &self.cave
+    }
+
+    pub fn into_cave(self) -> Cave {
+        self.cave
+    }
+
+    /// How many units of sand have come to rest so far
+    pub fn num_resting(&self) -> usize {
+        self.num_resting
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Run until a stop condition is hit, and return how many units came to rest
+    pub fn run(&mut self) -> usize {
+        for _ in self.by_ref() {}
+        self.num_resting
+    }
+}
+
+impl Iterator for SandSimulation {
+    type Item = SandEvent;
+
+    fn next(&mut self) -> Option<SandEvent> {
+        if self.finished {
+            return None;
+        }
+
+        let sand_coord = match self.falling {
+            Some(sand_coord) => sand_coord,
+            None => {
+                // generate more sand from the next source that isn't blocked
+                let i = match (0..self.sources.len())
+                    .map(|offset| (self.next_source + offset) % self.sources.len())
+                    .find(|&i| !self.blocked[i])
+                {
+                    Some(i) => i,
+                    None => {
+                        self.finished = true;
+                        return None;
+                    },
+                };
+                let source = self.sources[i];
+                self.next_source = (i + 1) % self.sources.len();
+                if self.cave.get(source) != Material::Air {
+                    self.blocked[i] = true;
+                    self.finished = self.blocked.iter().all(|&blocked| blocked);
+                    return Some(SandEvent::SourceBlocked(source));
+                }
+                self.falling = Some(source);
+                self.falling_from = i;
+                return Some(SandEvent::Spawned(source));
+            },
+        };
+
+        let event = match sand_step_with(sand_coord, &self.cave, self.fall_rules.directions()) {
             SandAction::Fall(new_coord) => {
-                //dbg!("falling", new_coord);
-                sand_coord = new_coord
+                self.falling = Some(new_coord);
+                SandEvent::FellTo(new_coord)
             },
             SandAction::AtRest(final_coord) => {
                 // this sand is done
-                grid[final_coord.0][final_coord.1] = Material::Sand;
-                //dbg!("sand at rest", final_coord);
-                // generate more sand
-                num_sand += 1;
-                sand_coord = (0, 500);
+                self.cave.set(final_coord, Material::Sand);
+                self.num_resting += 1;
+                self.falling = None;
+                SandEvent::CameToRest(final_coord)
             },
             SandAction::Abyss => {
-                //dbg!("fell in the abyss");
-                break
+                self.falling = None;
+                self.blocked[self.falling_from] = true;
+                SandEvent::FellIntoAbyss(sand_coord)
             },
-        }
+        };
+
+        let num_resting = self.num_resting;
+        self.finished = self.blocked.iter().all(|&blocked| blocked) || self.stop_conditions.iter().any(|condition| match condition {
+            StopCondition::Abyss => matches!(event, SandEvent::FellIntoAbyss(_)),
+            StopCondition::Grains(n) => num_resting >= *n,
+        });
+        Some(event)
     }
+}
 
-    num_sand
+// Part 1: units of sand until sand falls into the abyss
+#[aoc(day14, part1)]
+pub fn solve_part1(input: &Data) -> usize {
+    solve_part1_inner(input)
+}
+fn solve_part1_inner(input: &Data) -> usize {
+    SandSimulation::new(input.clone()).run()
 }
 
 // Part 2: there's a floor instead of an abyss
@@ -159,54 +460,173 @@ pub fn solve_part2(input: &Data) -> usize {
     solve_part2_inner(input)
 }
 fn solve_part2_inner(input: &Data) -> usize {
-    // Part 2: add a row of air and a row of rock underneath
-    // also make the grid bigger
-    let mut grid = input.clone();
-    let num_cols = grid[0].len();
-    // idk how many more cols we need to add
-    for row_index in 0..grid.len() {
-        let mut to_add = vec![Material::Air; num_cols];
-        grid[row_index].append(&mut to_add);
-    }
-    let num_cols = grid[0].len();
+    // Part 2: the floor is two rows below the lowest rock, and goes on forever
+    // so keep going until the source is blocked
+    SandSimulation::new(input.with_floor())
+        .with_stop_conditions(vec![])
+        .run()
+}
 
-    grid.push(vec![Material::Air; num_cols]);
-    grid.push(vec![Material::Rock; num_cols]);
+// Part 1 again, but each grain starts where the last one was just before it came to rest
+// The next grain follows exactly the same path until that point, so
+// keep the path as a stack and only simulate the last bit
+#[aoc(day14, part1, memoized)]
+pub fn solve_part1_memoized(input: &Data) -> usize {
+    let mut cave = input.clone();
+    pour_until_abyss(&mut cave)
+}
 
+/// Drop sand into the cave until it starts falling into the abyss
+/// Returns how many units came to rest, and leaves them in the cave
+pub fn pour_until_abyss(cave: &mut Cave) -> usize {
     let mut num_sand = 0;
-    // Sand comes from the point x=500 y=0
-    let mut sand_coord = (0, 500);
-    loop {
-        //dbg!(num_sand, sand_coord);
-        match sand_step(sand_coord, &grid) {
-            SandAction::Fall(new_coord) => {
-                //dbg!("falling", new_coord);
-                sand_coord = new_coord
-            },
+    let mut path = vec![SAND_SOURCE];
+    while let Some(&sand_coord) = path.last() {
+        match sand_step(sand_coord, cave) {
+            SandAction::Fall(new_coord) => path.push(new_coord),
             SandAction::AtRest(final_coord) => {
-                // this sand is done
-                grid[final_coord.0][final_coord.1] = Material::Sand;
+                cave.set(final_coord, Material::Sand);
                 num_sand += 1;
-                
-                // is the start blocked?
-                if 0 == final_coord.0 {
-                    //dbg!("blocked", num_sand, sand_coord);
-                    break
-                }
-
-                // generate more sand
-                sand_coord = (0, 500);
-            },
-            SandAction::Abyss => {
-                dbg!("fell in the abyss", num_sand, sand_coord);
-                unreachable!()
+                // the next grain picks up from the step before this one
+                path.pop();
             },
+            SandAction::Abyss => break,
         }
     }
 
     num_sand
 }
 
+// Part 2 again, without dropping any sand
+// With a floor, sand ends up in exactly the cells it can reach:
+// a cell gets sand if it isn't rock and one of the three cells above it has sand.
+// So fill the triangle under the source one row at a time
+#[aoc(day14, part2, fill)]
+pub fn solve_part2_fill(input: &Data) -> usize {
+    let cave = input.with_floor();
+    let floor = cave.floor().unwrap();
+    let (source_row, source_col) = SAND_SOURCE;
+
+    // d rows below the source, the triangle covers cols source_col-d..=source_col+d
+    let mut prev_row = vec![cave.get(SAND_SOURCE) == Material::Air];
+    let mut num_sand = prev_row.iter().filter(|&&sand| sand).count();
+    for row in source_row + 1..floor {
+        let width = prev_row.len() + 2;
+        let mut cur_row = vec![false; width];
+        for (i, sand) in cur_row.iter_mut().enumerate() {
+            let col = source_col - (row - source_row) + i as isize;
+            // cell i in this row is below cells i-2, i-1 and i of the previous row
+            let fed_from_above = (i.saturating_sub(2)..min(i + 1, prev_row.len()))
+                .any(|j| prev_row[j]);
+            *sand = fed_from_above && cave.get((row, col)) == Material::Air;
+        }
+        num_sand += cur_row.iter().filter(|&&sand| sand).count();
+        prev_row = cur_row;
+    }
+
+    num_sand
+}
+
+/// How much water ended up where after pour_water
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct WaterReport {
+    // water sitting in basins
+    pub settled: usize,
+    // water on its way through (including whatever runs off past the lowest rock)
+    pub flowing: usize,
+}
+
+/// Can water sit on top of this?
+fn holds_water(material: Material) -> bool {
+    matches!(material, Material::Rock | Material::StillWater | Material::Sand)
+}
+
+/// Pour water into the cave from the sand source
+pub fn pour_water(cave: &mut Cave) -> Result<WaterReport> {
+    pour_water_from(cave, SAND_SOURCE)
+}
+
+/// Pour water into the cave from source until nothing changes any more
+/// Water falls until it lands on something, then spreads sideways. If it's walled in
+/// on both sides it settles and the level rises, otherwise it flows off the edges
+/// and falls again. Anything falling past the lowest rock is gone.
+/// (no floors: water would spread along it forever)
+pub fn pour_water_from(cave: &mut Cave, source: (isize, isize)) -> Result<WaterReport> {
+    if cave.floor().is_some() {
+        return Err(anyhow!("can't pour water onto an infinite floor"));
+    }
+    let lowest_rock = cave.lowest_rock();
+
+    // places where water starts falling
+    let mut to_fall = vec![(source.0 + 1, source.1)];
+    while let Some((start_row, col)) = to_fall.pop() {
+        // fall until we land on something
+        let mut row = start_row;
+        loop {
+            if row > lowest_rock {
+                break;
+            }
+            cave.set((row, col), Material::FlowingWater);
+            match cave.get((row + 1, col)) {
+                Material::Air => row += 1,
+                // joins water that's already going somewhere
+                Material::FlowingWater => break,
+                _ => {
+                    spread(cave, (row, col), source.0, &mut to_fall);
+                    break;
+                },
+            }
+        }
+    }
+
+    Ok(WaterReport {
+        settled: cave.count(Material::StillWater),
+        flowing: cave.count(Material::FlowingWater),
+    })
+}
+
+/// Water has landed at coord: spread it sideways, and keep rising while it's walled in
+/// Any edges it spills over go onto to_fall
+fn spread(cave: &mut Cave, coord: (isize, isize), top_row: isize, to_fall: &mut Vec<(isize, isize)>) {
+    let (mut row, col) = coord;
+    while row > top_row {
+        // go each way until we hit a wall or run out of ground
+        let scan = |step: isize| {
+            let mut edge = col;
+            loop {
+                if !holds_water(cave.get((row + 1, edge))) {
+                    return (edge, false);
+                }
+                if matches!(cave.get((row, edge + step)), Material::Rock | Material::Sand) {
+                    return (edge, true);
+                }
+                edge += step;
+            }
+        };
+        let (left, left_walled) = scan(-1);
+        let (right, right_walled) = scan(1);
+
+        if left_walled && right_walled {
+            // a basin: fill this level and go up one
+            for c in left..=right {
+                cave.set((row, c), Material::StillWater);
+            }
+            row -= 1;
+            continue;
+        }
+
+        for c in left..=right {
+            cave.set((row, c), Material::FlowingWater);
+        }
+        for (edge, walled) in [(left, left_walled), (right, right_walled)] {
+            if !walled && cave.get((row + 1, edge)) == Material::Air {
+                to_fall.push((row + 1, edge));
+            }
+        }
+        break;
+    }
+}
+
 #[cfg(test)]
 mod test {
     const TEST_INPUT: &'static str =
@@ -228,4 +648,200 @@ r#"498,4 -> 498,6 -> 496,6
 
         assert_eq!(result, 93);
     }
+
+    #[test]
+    fn test_fast_solvers() {
+        let input = super::input_generator(TEST_INPUT).unwrap();
+        assert_eq!(super::solve_part1_memoized(&input), 24);
+        assert_eq!(super::solve_part2_fill(&input), 93);
+
+        let input = super::input_generator("500,30\n").unwrap();
+        assert_eq!(super::solve_part1_memoized(&input), 0);
+        assert_eq!(super::solve_part2_fill(&input), 32 * 32 - 1);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut cave = super::input_generator(TEST_INPUT).unwrap();
+        assert_eq!(cave.render(),
+r#"......+...
+..........
+..........
+..........
+....#...##
+....#...#.
+..###...#.
+........#.
+........#.
+#########.
+"#);
+        super::pour_until_abyss(&mut cave);
+        assert_eq!(cave.render(),
+r#"......+...
+..........
+......o...
+.....ooo..
+....#ooo##
+...o#ooo#.
+..###ooo#.
+....oooo#.
+.o.ooooo#.
+#########.
+"#);
+
+        // with the floor the picture grows to the whole pile
+        let cave = cave.with_floor();
+        let rendered = cave.render();
+        assert_eq!(rendered.lines().count(), 12);
+        assert_eq!(rendered.lines().last().unwrap(), "##########");
+
+        let image = cave.to_ppm();
+        assert!(image.starts_with(b"P6\n10 12\n255\n"));
+        assert_eq!(image.len(), b"P6\n10 12\n255\n".len() + 10 * 12 * 3);
+    }
+
+    #[test]
+    fn test_sand_simulation() {
+        use super::{SandEvent::*, SandSimulation, StopCondition};
+
+        let cave = super::input_generator(TEST_INPUT).unwrap();
+        let mut simulation = SandSimulation::new(cave.clone())
+            .with_stop_conditions(vec![StopCondition::Abyss, StopCondition::Grains(2)]);
+        let events: Vec<_> = simulation.by_ref().collect();
+        assert_eq!(events, vec![
+            Spawned((0, 500)), FellTo((1, 500)), FellTo((2, 500)), FellTo((3, 500)),
+            FellTo((4, 500)), FellTo((5, 500)), FellTo((6, 500)), FellTo((7, 500)),
+            FellTo((8, 500)), CameToRest((8, 500)),
+            Spawned((0, 500)), FellTo((1, 500)), FellTo((2, 500)), FellTo((3, 500)),
+            FellTo((4, 500)), FellTo((5, 500)), FellTo((6, 500)), FellTo((7, 500)),
+            FellTo((8, 499)), CameToRest((8, 499)),
+        ]);
+        assert!(simulation.is_finished());
+        assert_eq!(simulation.num_resting(), 2);
+
+        // sand from further left misses the rocks entirely
+        let mut simulation = SandSimulation::new(cave.clone()).with_source((0, 490));
+        assert_eq!(simulation.run(), 0);
+        assert_eq!(simulation.last(), None);
+
+        // with no stop conditions and no floor, it still stops at the abyss
+        let mut simulation = SandSimulation::new(cave.clone()).with_stop_conditions(vec![]);
+        assert_eq!(simulation.run(), 24);
+        assert!(simulation.is_finished());
+
+        // a floor right under the source fills up immediately
+        let mut simulation = SandSimulation::new(cave.with_floor())
+            .with_source((10, 500))
+            .with_stop_conditions(vec![]);
+        assert_eq!(simulation.next(), Some(Spawned((10, 500))));
+        assert_eq!(simulation.next(), Some(CameToRest((10, 500))));
+        assert_eq!(simulation.next(), Some(SourceBlocked((10, 500))));
+        assert_eq!(simulation.next(), None);
+    }
+
+    #[test]
+    fn test_sources_and_fall_rules() {
+        use super::{FallRules, Material, SandEvent, SandSimulation, StopCondition};
+
+        // two sources take turns
+        let cave = super::input_generator("0,5 -> 20,5\n").unwrap();
+        let mut simulation = SandSimulation::new(cave.clone())
+            .with_sources(vec![(0, 5), (0, 15)])
+            .with_stop_conditions(vec![StopCondition::Grains(3)]);
+        let rested: Vec<_> = simulation.by_ref()
+            .filter(|event| matches!(event, SandEvent::CameToRest(_)))
+            .collect();
+        assert_eq!(rested, vec![
+            SandEvent::CameToRest((4, 5)),
+            SandEvent::CameToRest((4, 15)),
+            SandEvent::CameToRest((4, 4)),
+        ]);
+
+        // and each one stops when it's blocked, while the other keeps going
+        let mut simulation = SandSimulation::new(cave.clone())
+            .with_sources(vec![(3, 5), (0, 15)])
+            .with_stop_conditions(vec![]);
+        let blocked: Vec<_> = simulation.by_ref()
+            .filter(|event| matches!(event, SandEvent::SourceBlocked(_)))
+            .collect();
+        assert_eq!(blocked, vec![SandEvent::SourceBlocked((3, 5)), SandEvent::SourceBlocked((0, 15))]);
+        // 3 + 1 under the first source, and a full 5 row pile under the second
+        assert_eq!(simulation.num_resting(), 4 + 25);
+
+        // straight down only makes towers
+        let rules = FallRules::new(vec![(1, 0)]).unwrap();
+        let mut simulation = SandSimulation::new(cave.clone())
+            .with_source((0, 5))
+            .with_fall_rules(rules)
+            .with_stop_conditions(vec![]);
+        assert_eq!(simulation.run(), 5);
+        assert!((0..5).all(|row| simulation.cave().get((row, 5)) == Material::Sand));
+
+        // sand has to go down
+        assert!(FallRules::new(vec![(1, 0), (0, 1)]).is_err());
+
+        // sand next to the edge of the coordinates doesn't wrap around or panic
+        let cave = super::input_generator(&format!("{},1 -> {},1\n", isize::MIN, isize::MIN + 1)).unwrap();
+        let mut simulation = SandSimulation::new(cave)
+            .with_source((0, isize::MIN))
+            .with_fall_rules(FallRules::new(vec![(1, -1)]).unwrap())
+            .with_stop_conditions(vec![]);
+        assert_eq!(simulation.run(), 1);
+    }
+
+    #[test]
+    fn test_water() {
+        let mut cave = super::input_generator(
+r#"495,2 -> 495,7 -> 501,7 -> 501,3
+498,2 -> 498,4
+506,1 -> 506,2
+498,10 -> 498,13 -> 504,13 -> 504,10
+"#).unwrap();
+        let report = super::pour_water(&mut cave).unwrap();
+        assert_eq!(report, super::WaterReport { settled: 29, flowing: 28 });
+        assert_eq!(cave.render(),
+r#".....+......
+.....|.....#
+#..#||||...#
+#..#~~#|....
+#..#~~#|....
+#~~~~~#|....
+#~~~~~#|....
+#######|....
+.......|....
+..|||||||||.
+..|#~~~~~#|.
+..|#~~~~~#|.
+..|#~~~~~#|.
+..|#######|.
+"#);
+
+        let mut cave = super::input_generator(TEST_INPUT).unwrap().with_floor();
+        assert!(super::pour_water(&mut cave).is_err());
+    }
+
+    #[test]
+    fn test_sparse_cave() {
+        use super::Material;
+
+        // rocks left of x=0 and above y=0 are fine
+        let cave = super::input_generator("-5,-2 -> -3,-2 -> -3,1\n").unwrap();
+        assert_eq!(cave.get((-2, -4)), Material::Rock);
+        assert_eq!(cave.get((1, -3)), Material::Rock);
+        assert_eq!(cave.get((0, -4)), Material::Air);
+        assert_eq!(cave.lowest_rock(), 1);
+        assert_eq!(cave.bounds(), Some((-2..=1, -5..=-3)));
+
+        // a lone rock far below the source: the pile is much wider than the rock
+        // and fills the whole triangle down to the floor, except the rock itself
+        let input = super::input_generator("500,30\n").unwrap();
+        assert_eq!(super::solve_part1(&input), 0);
+        assert_eq!(super::solve_part2(&input), 32 * 32 - 1);
+
+        // all the rock is above the source: the floor goes two rows below the source instead
+        let input = super::input_generator("490,-5 -> 510,-5\n").unwrap();
+        assert_eq!(input.with_floor().floor(), Some(2));
+        assert_eq!(super::solve_part2(&input), 4);
+        assert_eq!(super::solve_part2_fill(&input), 4);
+    }
 }
\ No newline at end of file
@@ -1,8 +1,8 @@
-use std::{collections::HashSet, ops::RangeInclusive};
+use std::{collections::{BTreeSet, HashMap, HashSet}, ops::RangeInclusive};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 pub struct Sensor{
     x: isize,
@@ -10,7 +10,7 @@ pub struct Sensor{
     radius: isize, //Manhattan distance to closest beacon
 }
 
-#[derive(Hash, PartialEq, Eq)]
+#[derive(Hash, PartialEq, Eq, Clone, Copy)]
 pub struct Beacon{
     x: isize,
     y: isize,
@@ -33,24 +33,7 @@ fn input_generator_inner(input: &str) -> Result<Data> {
     // input has duplicate beacons (closest to more than one sensor)
     let mut beacons = HashSet::new();
     for line in input.lines() {
-        // format is
-        // Sensor at x=2, y=18: closest beacon is at x=-2, y=15
-        // +x goes right, +y goes down
-        let (sensor_line, beacon_line) = line.split_once(": ").unwrap();
-        
-        let (_, beacon_line) = beacon_line.split_once("at ").unwrap();
-        let (x,y) = beacon_line.split_once(", ").unwrap();
-        let x: isize = x[2..].parse()?;
-        let y: isize = y[2..].parse()?;
-        let beacon = Beacon {x,y};
-
-        let (_, sensor_line) = sensor_line.split_once("at ").unwrap();
-        let (x,y) = sensor_line.split_once(", ").unwrap();
-        let x: isize = x[2..].parse()?;
-        let y: isize = y[2..].parse()?;
-        let radius = manhattan((x,y), (beacon.x, beacon.y));
-        let sensor = Sensor{x, y, radius};
-
+        let (sensor, beacon) = parse_line(line)?;
         sensors.push(sensor);
         beacons.insert(beacon);
     }
@@ -58,6 +41,28 @@ fn input_generator_inner(input: &str) -> Result<Data> {
     Ok((sensors, beacons))
 }
 
+fn parse_line(line: &str) -> Result<(Sensor, Beacon)> {
+    // format is
+    // Sensor at x=2, y=18: closest beacon is at x=-2, y=15
+    // +x goes right, +y goes down
+    let (sensor_line, beacon_line) = line.split_once(": ").unwrap();
+    
+    let (_, beacon_line) = beacon_line.split_once("at ").unwrap();
+    let (x,y) = beacon_line.split_once(", ").unwrap();
+    let x: isize = x[2..].parse()?;
+    let y: isize = y[2..].parse()?;
+    let beacon = Beacon {x,y};
+
+    let (_, sensor_line) = sensor_line.split_once("at ").unwrap();
+    let (x,y) = sensor_line.split_once(", ").unwrap();
+    let x: isize = x[2..].parse()?;
+    let y: isize = y[2..].parse()?;
+    let radius = manhattan((x,y), (beacon.x, beacon.y));
+    let sensor = Sensor{x, y, radius};
+
+    Ok((sensor, beacon))
+}
+
 /// Add a new range to existing ranges
 fn add_range(ranges: &mut Vec<RangeInclusive<isize>>, new_range: RangeInclusive<isize>) {
     let mut overlap_indices = vec![];
@@ -127,13 +132,288 @@ fn get_sensor_coverage(sensors: &Vec<Sensor>, y: isize) -> Vec<RangeInclusive<is
     covered_ranges
 }
 
+/// The covered positions in a row, clipped to xs and sorted left to right
+fn get_clipped_coverage(sensors: &Vec<Sensor>, y: isize, xs: &RangeInclusive<isize>) -> Vec<RangeInclusive<isize>>
+{
+    clip_ranges(&get_sensor_coverage(sensors, y), xs)
+}
+
+/// Clip ranges to xs and sort them left to right
+fn clip_ranges(ranges: &[RangeInclusive<isize>], xs: &RangeInclusive<isize>) -> Vec<RangeInclusive<isize>>
+{
+    let mut clipped: Vec<RangeInclusive<isize>> = ranges
+        .iter()
+        .map(|range| *range.start().max(xs.start())..=*range.end().min(xs.end()))
+        .filter(|range| !range.is_empty())
+        .collect();
+    clipped.sort_by_key(|range| *range.start());
+    clipped
+}
+
+/// How many cells in row y within xs are covered
+fn row_covered(sensors: &Vec<Sensor>, y: isize, xs: &RangeInclusive<isize>) -> isize {
+    get_clipped_coverage(sensors, y, xs).iter()
+        .map(|range| range.end() - range.start() + 1)
+        .sum()
+}
+
+/// Split ys into bands of rows where the covered cells in xs change steadily
+/// Every diamond edge is a line x = c + slope * y with slope +1 or -1, and so are
+/// the edges of xs (slope 0). Between the rows where a diamond starts, turns or ends,
+/// and the rows where two of those lines cross, the covered ranges keep their order
+/// and just grow or shrink by a fixed amount each row, so the number of covered cells
+/// is linear in y within a band. Rows near those events get a band of their own.
+fn row_bands(sensors: &Vec<Sensor>, xs: &RangeInclusive<isize>, ys: &RangeInclusive<isize>) -> Vec<RangeInclusive<isize>> {
+    if ys.is_empty() {
+        return vec![];
+    }
+
+    let mut lines = vec![(*xs.start(), 0), (*xs.end(), 0)];
+    let mut events = BTreeSet::new();
+    for sensor in sensors {
+        let (x, y, r) = (sensor.x, sensor.y, sensor.radius);
+        // top half left and right, then bottom half left and right
+        lines.extend([(x - r + y, -1), (x + r - y, 1), (x - r - y, 1), (x + r + y, -1)]);
+        events.extend([y - r, y, y + r]);
+    }
+    for (i, &(c1, slope1)) in lines.iter().enumerate() {
+        for &(c2, slope2) in &lines[i + 1..] {
+            if slope1 == slope2 {
+                continue;
+            }
+            // c1 + slope1 * y == c2 + slope2 * y, which can be half way between rows
+            let (mut num, mut den) = (c2 - c1, slope1 - slope2);
+            if den < 0 {
+                (num, den) = (-num, -den);
+            }
+            events.insert(num.div_euclid(den));
+        }
+    }
+
+    // the rows around every event are bands by themselves
+    let mut single_rows = BTreeSet::from([*ys.start(), *ys.end()]);
+    for event in events {
+        single_rows.extend((event - 1..=event + 2).filter(|row| ys.contains(row)));
+    }
+
+    let mut bands = vec![];
+    let mut prev: Option<isize> = None;
+    for row in single_rows {
+        if let Some(prev) = prev {
+            if row > prev + 1 {
+                bands.push(prev + 1..=row - 1);
+            }
+        }
+        bands.push(row..=row);
+        prev = Some(row);
+    }
+    bands
+}
+
+/// How many cells in the rectangle xs by ys cannot contain a beacon
+/// (before taking out the beacons)
+/// Works a band of rows at a time instead of row by row, see row_bands
+fn covered_in_region(sensors: &Vec<Sensor>, xs: &RangeInclusive<isize>, ys: &RangeInclusive<isize>) -> isize {
+    row_bands(sensors, xs, ys).into_iter()
+        .map(|band| {
+            let (first, last) = (*band.start(), *band.end());
+            // linear in between, so add it up like an arithmetic series
+            (row_covered(sensors, first, xs) + row_covered(sensors, last, xs)) * (last - first + 1) / 2
+        })
+        .sum()
+}
+
+/// How many cells in the rectangle xs by ys cannot contain a beacon
+/// Like part 1, cells that already have a beacon don't count
+pub fn region_coverage(input: &Data, xs: RangeInclusive<isize>, ys: RangeInclusive<isize>) -> usize {
+    let (sensors, beacons) = input;
+
+    let mut num_covered = covered_in_region(sensors, &xs, &ys);
+
+    // do not double-count the spots where beacons already are
+    for beacon in beacons {
+        if xs.contains(&beacon.x) && ys.contains(&beacon.y) {
+            num_covered -= 1;
+        }
+    }
+
+    num_covered as usize
+}
+
+/// Every cell in the rectangle xs by ys that no sensor covers, in reading order
+pub fn region_uncovered(input: &Data, xs: RangeInclusive<isize>, ys: RangeInclusive<isize>) -> Vec<(isize, isize)> {
+    let (sensors, _) = input;
+    let width = (xs.end() - xs.start() + 1).max(0);
+
+    let mut uncovered = vec![];
+    for band in row_bands(sensors, &xs, &ys) {
+        // if both ends of a band are fully covered, so is everything in between
+        if row_covered(sensors, *band.start(), &xs) == width
+                && row_covered(sensors, *band.end(), &xs) == width {
+            continue;
+        }
+        for y in band {
+            // walk the gaps between the covered ranges
+            let mut x = *xs.start();
+            for range in get_clipped_coverage(sensors, y, &xs) {
+                uncovered.extend((x..*range.start()).map(|x| (x, y)));
+                x = range.end() + 1;
+            }
+            uncovered.extend((x..=*xs.end()).map(|x| (x, y)));
+        }
+    }
+
+    uncovered
+}
+
+/// Sensors and beacons that can be added and removed on the fly,
+/// for trying out different sensor placements
+/// Remembers the coverage of every row it has been asked about, and keeps
+/// those rows up to date as sensors come and go
+#[derive(Default)]
+pub struct SensorNetwork {
+    sensors: Vec<Sensor>,
+    // the beacon closest to each sensor, same order as sensors
+    closest_beacons: Vec<Beacon>,
+    // how many sensors each beacon is closest to
+    beacons: HashMap<Beacon, usize>,
+    // covered ranges for the rows we've looked at
+    row_cache: HashMap<isize, Vec<RangeInclusive<isize>>>,
+}
+
+impl SensorNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a network from the puzzle input
+    pub fn from_input(input: &str) -> Result<Self> {
+        let mut network = Self::new();
+        for line in input.lines() {
+            let (sensor, beacon) = parse_line(line)?;
+            network.add_sensor((sensor.x, sensor.y), (beacon.x, beacon.y));
+        }
+        Ok(network)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sensors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sensors.is_empty()
+    }
+
+    /// Add a sensor whose closest beacon is at beacon
+    pub fn add_sensor(&mut self, sensor: (isize, isize), beacon: (isize, isize)) {
+        let radius = manhattan(sensor, beacon);
+        let (x, y) = sensor;
+
+        // the new diamond only ever adds coverage, so the cached rows can just take its range
+        for (&row, ranges) in self.row_cache.iter_mut() {
+            let delta_x = radius - (y - row).abs();
+            if delta_x >= 0 {
+                add_range(ranges, x - delta_x..=x + delta_x);
+            }
+        }
+
+        let beacon = Beacon { x: beacon.0, y: beacon.1 };
+        *self.beacons.entry(beacon).or_insert(0) += 1;
+        self.sensors.push(Sensor { x, y, radius });
+        self.closest_beacons.push(beacon);
+    }
+
+    /// Remove the sensor at the given position
+    /// Returns false if there wasn't one there
+    pub fn remove_sensor(&mut self, sensor: (isize, isize)) -> bool {
+        let index = match self.sensors.iter().position(|s| (s.x, s.y) == sensor) {
+            Some(index) => index,
+            None => return false,
+        };
+        let removed = self.sensors.remove(index);
+        let beacon = self.closest_beacons.remove(index);
+
+        // the beacon is still known as long as some other sensor saw it
+        let count = self.beacons.get_mut(&beacon).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.beacons.remove(&beacon);
+        }
+
+        // can't take a diamond back out of a union,
+        // so forget the rows it touched and work them out again when asked
+        self.row_cache.retain(|&row, _| (removed.y - row).abs() > removed.radius);
+        true
+    }
+
+    /// Covered ranges in a row, from the cache if we can
+    fn row_ranges(&mut self, y: isize) -> &Vec<RangeInclusive<isize>> {
+        let sensors = &self.sensors;
+        self.row_cache.entry(y).or_insert_with(|| get_sensor_coverage(sensors, y))
+    }
+
+    /// Like part 1: how many positions in row y cannot contain a beacon
+    pub fn row_coverage(&mut self, y: isize) -> usize {
+        let mut num_covered: isize = self.row_ranges(y).iter()
+            .map(|range| range.end() - range.start() + 1)
+            .sum();
+        num_covered -= self.beacons.keys().filter(|beacon| beacon.y == y).count() as isize;
+        num_covered as usize
+    }
+
+    /// Like region_coverage, for the rectangle xs by ys
+    /// (doesn't use the row cache: it goes a band of rows at a time instead)
+    pub fn region_coverage(&self, xs: RangeInclusive<isize>, ys: RangeInclusive<isize>) -> usize {
+        let mut num_covered = covered_in_region(&self.sensors, &xs, &ys);
+        num_covered -= self.beacons.keys()
+            .filter(|beacon| xs.contains(&beacon.x) && ys.contains(&beacon.y))
+            .count() as isize;
+        num_covered as usize
+    }
+
+    /// Positions of the sensors whose range includes point, for debugging
+    pub fn sensors_covering(&self, point: (isize, isize)) -> Vec<(isize, isize)> {
+        self.sensors.iter()
+            .filter(|sensor| manhattan(point, (sensor.x, sensor.y)) <= sensor.radius)
+            .map(|sensor| (sensor.x, sensor.y))
+            .collect()
+    }
+}
+
+/// The numbers that differ between the example and the real puzzle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    /// the row part 1 checks
+    pub row: isize,
+    /// part 2 searches x and y in [0, max_coord]
+    pub max_coord: isize,
+    /// tuning frequency is x * tuning_multiplier + y
+    pub tuning_multiplier: isize,
+}
+
+impl Params {
+    pub const REAL: Params = Params { row: 2_000_000, max_coord: 4_000_000, tuning_multiplier: 4_000_000 };
+    pub const EXAMPLE: Params = Params { row: 10, max_coord: 20, tuning_multiplier: 4_000_000 };
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params::REAL
+    }
+}
+
 // Part 1: in a given row, how many positions cannot contain a beacon
 #[aoc(day15, part1)]
 pub fn solve_part1(input: &Data) -> usize {
-    solve_part1_inner(input, 2_000_000)
+    solve_part1_inner(input, &Params::REAL)
+}
+/// Part 1 for the row in params
+pub fn solve_part1_with(input: &Data, params: &Params) -> usize {
+    solve_part1_inner(input, params)
 }
-fn solve_part1_inner(input: &Data, y_to_check: isize) -> usize {
+fn solve_part1_inner(input: &Data, params: &Params) -> usize {
     let (sensors, beacons) = input.clone();
+    let y_to_check = params.row;
 
     let covered_ranges = get_sensor_coverage(sensors, y_to_check);
 
@@ -154,50 +434,174 @@ fn solve_part1_inner(input: &Data, y_to_check: isize) -> usize {
 
 // Part 2
 // There is only one possible location where a secret beacon could be
-// with x and y coordinates in the range [0, 4_000_000]
+// with x and y coordinates in the range [0, max_coord] (4_000_000 for the real puzzle)
 // Where is it?
-// "Tuning Frequency": x*4_000_000 + y
+// "Tuning Frequency": x*tuning_multiplier + y (4_000_000 again for the real puzzle)
 
-fn tuning(x: isize, y: isize) -> usize {
-    (x*4_000_000 + y) as usize
+fn tuning(x: isize, y: isize, multiplier: isize) -> Result<usize> {
+    x.checked_mul(multiplier)
+        .and_then(|frequency| frequency.checked_add(y))
+        .and_then(|frequency| usize::try_from(frequency).ok())
+        .ok_or(anyhow!("tuning frequency of {},{} doesn't fit in a usize", x, y))
 }
 
 #[aoc(day15, part2)]
-pub fn solve_part2(input: &Data) -> usize {
-    solve_part2_inner(input, 4_000_000)
+pub fn solve_part2(input: &Data) -> Result<usize> {
+    solve_part2_inner(input, &Params::REAL)
 }
-fn solve_part2_inner(input: &Data, max_coord: isize) -> usize {
+/// Part 2 for the search area and tuning frequency in params
+pub fn solve_part2_with(input: &Data, params: &Params) -> Result<usize> {
+    solve_part2_inner(input, params)
+}
+fn solve_part2_inner(input: &Data, params: &Params) -> Result<usize> {
     let (sensors, _) = input.clone();
+    let max_coord = params.max_coord;
+    let tuning = |x, y| tuning(x, y, params.tuning_multiplier);
 
+    let xs = 0..=max_coord;
     for y in 0..=max_coord {
-        let covered_ranges = get_sensor_coverage(sensors, y);
-
-        // does the first sensor start after 0?
-        if covered_ranges.first().unwrap().start() > &0 {
-            return tuning(covered_ranges.first().unwrap().start()-1, y);
-        }
-        // does the last sensor end before the max coord?
-        if covered_ranges.last().unwrap().end() < &max_coord {
-            return tuning(covered_ranges.last().unwrap().end() + 1, y);
-        }
-
-        // look at the gaps between ranges
-        let mut prev: Option<RangeInclusive<isize>> = None; 
-        for range in covered_ranges {
-            if let Some(prev_range) = prev {
-                // if there's a gap between the end of prev and the start of this
-                if range.start() - prev_range.end() > 1 {
-                    let x = prev_range.end() + 1;
-                    return tuning(x,y);
-                }
+        // walk the gaps between the covered ranges, left to right
+        // (the ranges can overlap, or be missing altogether in a row no sensor reaches)
+        let mut x = 0;
+        for range in get_clipped_coverage(sensors, y, &xs) {
+            if *range.start() > x {
+                return tuning(x, y);
+            }
+            x = x.max(range.end() + 1);
+        }
+        if x <= max_coord {
+            return tuning(x, y);
+        }
+    }
+
+    Err(anyhow!("no gap found in [0, {}]", max_coord))
+}
+
+// Part 2, but without scanning every row
+// The lone gap has to be just outside some sensor's range, so it sits on the
+// lines one step past the edges of the sensor diamonds. In rotated coordinates
+// (x+y and x-y) those edges are axis-aligned, so the candidates are where
+// an x+y edge of one sensor crosses an x-y edge of another.
+// (the row scan above is kept as the reference implementation)
+#[aoc(day15, part2, diamonds)]
+pub fn solve_part2_diamonds(input: &Data) -> Result<usize> {
+    solve_part2_diamonds_inner(input, &Params::REAL)
+}
+fn solve_part2_diamonds_inner(input: &Data, params: &Params) -> Result<usize> {
+    match find_distress_beacon(input, params.max_coord) {
+        Some((x, y)) => tuning(x, y, params.tuning_multiplier),
+        None => Err(anyhow!("no gap found in [0, {}]", params.max_coord)),
+    }
+}
+
+/// Where the distress beacon is, with x and y in [0, max_coord]
+pub fn find_distress_beacon(input: &Data, max_coord: isize) -> Option<(isize, isize)> {
+    let (sensors, _) = input;
+
+    // the lines just outside each diamond
+    let mut sums = vec![]; // x + y = c
+    let mut diffs = vec![]; // x - y = c
+    for sensor in sensors {
+        let sum = sensor.x + sensor.y;
+        let diff = sensor.x - sensor.y;
+        sums.push(sum - sensor.radius - 1);
+        sums.push(sum + sensor.radius + 1);
+        diffs.push(diff - sensor.radius - 1);
+        diffs.push(diff + sensor.radius + 1);
+    }
+
+    let mut candidates = vec![];
+    for &sum in &sums {
+        for &diff in &diffs {
+            // only lattice points count
+            if (sum + diff) % 2 == 0 {
+                candidates.push(((sum + diff) / 2, (sum - diff) / 2));
             }
+        }
+    }
+    // the gap might be wedged against the edge of the search area instead
+    for edge in [0, max_coord] {
+        for &sum in &sums {
+            candidates.push((edge, sum - edge));
+            candidates.push((sum - edge, edge));
+        }
+        for &diff in &diffs {
+            candidates.push((edge, edge - diff));
+            candidates.push((diff + edge, edge));
+        }
+        candidates.push((edge, 0));
+        candidates.push((edge, max_coord));
+    }
 
-            prev = Some(range);
+    for (x, y) in candidates {
+        if x < 0 || y < 0 || x > max_coord || y > max_coord {
+            continue;
+        }
+        if sensors.iter().all(|sensor| manhattan((x, y), (sensor.x, sensor.y)) > sensor.radius) {
+            return Some((x, y));
         }
     }
-    
-    dbg!("Gap not found?");
-    unreachable!()
+
+    None
+}
+
+/// Draw the map like the puzzle does for the rectangle xs by ys:
+/// S for sensors, B for beacons, # for positions that can't have a beacon,
+/// X for the distress beacon if there is one, and . for everything else
+/// Columns are labelled every 5 (top to bottom), rows on the left
+pub fn render_coverage(input: &Data, xs: RangeInclusive<isize>, ys: RangeInclusive<isize>,
+    distress: Option<(isize, isize)>) -> String
+{
+    let (sensors, beacons) = input;
+
+    let row_label_width = ys.clone().map(|y| y.to_string().len()).max().unwrap_or(0);
+
+    // column labels are written vertically, bottom-aligned
+    let column_labels: Vec<String> = xs.clone()
+        .map(|x| if x % 5 == 0 { x.to_string() } else { String::new() })
+        .collect();
+    let label_height = column_labels.iter().map(|label| label.len()).max().unwrap_or(0);
+    let mut rendered = String::new();
+    for line in 0..label_height {
+        let mut header = " ".repeat(row_label_width + 1);
+        for label in &column_labels {
+            let padding = label_height - label.len();
+            if line < padding {
+                header.push(' ');
+            }
+            else {
+                header.push(label.as_bytes()[line - padding] as char);
+            }
+        }
+        rendered.push_str(header.trim_end());
+        rendered.push('\n');
+    }
+
+    for y in ys {
+        let covered = get_sensor_coverage(sensors, y);
+        rendered.push_str(&format!("{:>width$} ", y, width = row_label_width));
+        for x in xs.clone() {
+            let c = if sensors.iter().any(|sensor| (sensor.x, sensor.y) == (x, y)) {
+                'S'
+            }
+            else if beacons.contains(&Beacon { x, y }) {
+                'B'
+            }
+            else if distress == Some((x, y)) {
+                'X'
+            }
+            else if covered.iter().any(|range| range.contains(&x)) {
+                '#'
+            }
+            else {
+                '.'
+            };
+            rendered.push(c);
+        }
+        rendered.push('\n');
+    }
+
+    rendered
 }
 
 #[cfg(test)]
@@ -221,7 +625,7 @@ Sensor at x=20, y=1: closest beacon is at x=15, y=3
     #[test]
     fn test_part1_example() {
         let input = super::input_generator(TEST_INPUT).unwrap();
-        let result = super::solve_part1_inner(&input, 10);
+        let result = super::solve_part1_inner(&input, &super::Params::EXAMPLE);
 
         assert_eq!(result, 26);
     }
@@ -229,8 +633,128 @@ Sensor at x=20, y=1: closest beacon is at x=15, y=3
     #[test]
     fn test_part2_example() {
         let input = super::input_generator(TEST_INPUT).unwrap();
-        let result = super::solve_part2_inner(&input, 20);
+        let result = super::solve_part2_inner(&input, &super::Params::EXAMPLE).unwrap();
 
         assert_eq!(result, 56000011);
     }
+
+    #[test]
+    fn test_params() {
+        use super::Params;
+
+        let input = super::input_generator(TEST_INPUT).unwrap();
+        assert_eq!(Params::default(), Params::REAL);
+        assert_eq!(super::solve_part1_with(&input, &Params::EXAMPLE), 26);
+        assert_eq!(super::solve_part2_with(&input, &Params::EXAMPLE).unwrap(), 56000011);
+        assert_eq!(super::solve_part2_diamonds_inner(&input, &Params::EXAMPLE).unwrap(), 56000011);
+
+        let params = Params { row: 11, tuning_multiplier: 100, ..Params::EXAMPLE };
+        assert_eq!(super::solve_part1_with(&input, &params), 28);
+        assert_eq!(super::solve_part2_with(&input, &params).unwrap(), 1411);
+
+        // a row no sensor reaches at all
+        let input = super::input_generator("Sensor at x=0, y=5: closest beacon is at x=1, y=5\n").unwrap();
+        let params = Params { max_coord: 10, ..Params::EXAMPLE };
+        assert_eq!(super::solve_part2_with(&input, &params).unwrap(), 0);
+
+        // two diamonds whose ranges only just touch in row 0, and come in unsorted
+        let input = super::input_generator(
+r#"Sensor at x=7, y=0: closest beacon is at x=9, y=0
+Sensor at x=2, y=0: closest beacon is at x=4, y=0
+"#).unwrap();
+        let params = Params { max_coord: 9, ..Params::EXAMPLE };
+        assert_eq!(super::solve_part2_with(&input, &params).unwrap(), 1);
+
+        assert!(super::tuning(isize::MAX, 0, 4_000_000).is_err());
+        assert!(super::tuning(-1, 0, 4_000_000).is_err());
+    }
+
+    #[test]
+    fn test_region_queries() {
+        let input = super::input_generator(TEST_INPUT).unwrap();
+
+        // a single row is the same as part 1
+        assert_eq!(super::region_coverage(&input, -100..=100, 10..=10), 26);
+
+        // everything but the distress beacon and the 3 known beacons
+        assert_eq!(super::region_coverage(&input, 0..=20, 0..=20), 21 * 21 - 1 - 3);
+        assert_eq!(super::region_uncovered(&input, 0..=20, 0..=20), vec![(14, 11)]);
+        assert_eq!(super::region_uncovered(&input, 24..=25, -12..=-11),
+            vec![(24, -12), (25, -12), (24, -11), (25, -11)]);
+
+        // the same as checking every cell
+        let brute_force = |xs: std::ops::RangeInclusive<isize>, ys: std::ops::RangeInclusive<isize>| {
+            let (sensors, beacons) = &input;
+            ys.flat_map(|y| xs.clone().map(move |x| (x, y)))
+                .filter(|&(x, y)| sensors.iter().any(|s| super::manhattan((x, y), (s.x, s.y)) <= s.radius))
+                .filter(|&(x, y)| !beacons.contains(&super::Beacon { x, y }))
+                .count()
+        };
+        for (xs, ys) in [(-10..=30, -10..=30), (3..=17, 5..=9), (12..=12, -20..=40), (-3..=-3, -3..=-3)] {
+            assert_eq!(super::region_coverage(&input, xs.clone(), ys.clone()), brute_force(xs, ys));
+        }
+
+        // huge regions are quick, since every diamond is well inside this one
+        assert_eq!(super::region_coverage(&input, -4_000_000..=4_000_000, -4_000_000..=4_000_000),
+            super::region_coverage(&input, -100..=100, -100..=100));
+    }
+
+    #[test]
+    fn test_sensor_network() {
+        let mut network = super::SensorNetwork::from_input(TEST_INPUT).unwrap();
+        assert_eq!(network.len(), 14);
+        assert_eq!(network.row_coverage(10), 26);
+        assert_eq!(network.region_coverage(0..=20, 0..=20), 21 * 21 - 1 - 3);
+        assert_eq!(network.sensors_covering((14, 11)), vec![]);
+        assert_eq!(network.sensors_covering((2, 10)), vec![(8, 7), (2, 0), (0, 11)]);
+
+        // plug the gap: the new beacon sits on row 11 but not row 10
+        network.add_sensor((14, 11), (14, 12));
+        assert_eq!(network.row_coverage(10), 26);
+        assert_eq!(network.region_coverage(0..=20, 0..=20), 21 * 21 - 4);
+
+        // and take it back out again
+        assert!(network.remove_sensor((14, 11)));
+        assert!(!network.remove_sensor((14, 11)));
+        assert_eq!(network.region_coverage(0..=20, 0..=20), 21 * 21 - 1 - 3);
+
+        // the beacon at 10,16 is shared by 4 sensors, so it stays after removing one
+        assert!(network.remove_sensor((9, 16)));
+        assert_eq!(network.row_coverage(16), 
+            super::SensorNetwork::from_input(&TEST_INPUT.replace(
+                "Sensor at x=9, y=16: closest beacon is at x=10, y=16\n", "")).unwrap().row_coverage(16));
+    }
+
+    #[test]
+    fn test_render_coverage() {
+        let input = super::input_generator(TEST_INPUT).unwrap();
+        let distress = super::find_distress_beacon(&input, 20);
+        assert_eq!(distress, Some((14, 11)));
+
+        let expected =
+r#"               1    1    2
+     0    5    0    5    0
+ 9 .########################
+10 ####B####################
+11 ##S#############X########
+12 #########################
+"#;
+        assert_eq!(super::render_coverage(&input, -2..=22, 9..=12, distress), expected);
+    }
+
+    #[test]
+    fn test_part2_diamonds() {
+        let input = super::input_generator(TEST_INPUT).unwrap();
+        let result = super::solve_part2_diamonds_inner(&input, &super::Params::EXAMPLE).unwrap();
+
+        assert_eq!(result, super::solve_part2_inner(&input, &super::Params::EXAMPLE).unwrap());
+
+        // a gap in the corner of the search area, not between two diamonds
+        let corner_input = super::input_generator(
+            "Sensor at x=0, y=0: closest beacon is at x=3, y=0\n").unwrap();
+        let params = super::Params { max_coord: 2, ..super::Params::EXAMPLE };
+        let expected = super::tuning(2, 2, params.tuning_multiplier).unwrap();
+        assert_eq!(super::solve_part2_diamonds_inner(&corner_input, &params).unwrap(), expected);
+        assert_eq!(super::solve_part2_inner(&corner_input, &params).unwrap(), expected);
+    }
 }
\ No newline at end of file
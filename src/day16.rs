@@ -1,10 +1,10 @@
-use std::{vec, collections::HashMap, cmp::max};
+use std::{vec, collections::{HashMap, HashSet}, cmp::max, fmt, time::{Duration, Instant}};
 
-use petgraph::{Graph, Undirected, prelude::NodeIndex};
+use petgraph::{Graph, Undirected, prelude::NodeIndex, algo::dijkstra};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 // the map of valve indices to flow rates,
 // map of valve names to valve indices,
@@ -13,6 +13,145 @@ type Data = (HashMap<NodeIndex,usize>,
              HashMap<String,NodeIndex>,
             Graph<String, usize, Undirected>);
 
+// where we start
+const START_VALVE: &str = "AA";
+
+// Something wrong with the input, found before we try to build the graph
+// Line numbers are 1-based
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValveProblem {
+    // the line doesn't match the expected format at all
+    Malformed { line: usize, message: String },
+    // the same valve is defined twice
+    DuplicateValve { line: usize, name: String, first_line: usize },
+    // a tunnel leads to a valve that is never defined
+    UnknownDestination { line: usize, from: String, to: String },
+    // A lists a tunnel to B, but B doesn't list one back to A
+    AsymmetricTunnel { line: usize, from: String, to: String },
+    // there's no valve AA to start from
+    MissingStart,
+    // can't get to this valve from AA (only a warning)
+    Unreachable { line: usize, name: String },
+}
+
+impl fmt::Display for ValveProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValveProblem::Malformed { line, message } =>
+                write!(f, "line {}: {}", line, message),
+            ValveProblem::DuplicateValve { line, name, first_line } =>
+                write!(f, "line {}: valve {} already defined on line {}", line, name, first_line),
+            ValveProblem::UnknownDestination { line, from, to } =>
+                write!(f, "line {}: valve {} has a tunnel to undefined valve {}", line, from, to),
+            ValveProblem::AsymmetricTunnel { line, from, to } =>
+                write!(f, "line {}: valve {} has a tunnel to {}, but {} has no tunnel back", line, from, to, to),
+            ValveProblem::MissingStart =>
+                write!(f, "no start valve {}", START_VALVE),
+            ValveProblem::Unreachable { line, name } =>
+                write!(f, "line {}: valve {} can't be reached from {}", line, name, START_VALVE),
+        }
+    }
+}
+
+// Everything validate_input found
+// Errors stop us from building the graph, warnings don't
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub errors: Vec<ValveProblem>,
+    pub warnings: Vec<ValveProblem>,
+}
+
+// Split a line into its valve name, flow rate, and tunnel destinations
+fn parse_line(line: &str) -> Result<(String, usize, Vec<String>)> {
+    let (valve_str, tunnel_str) = line.split_once("; ")
+        .ok_or(anyhow!("missing \"; \" between valve and tunnels"))?;
+
+    // Valve AA has flow rate=0
+    let (valve_str, flow_rate_str) = valve_str.split_once(" has flow rate=")
+        .ok_or(anyhow!("missing \" has flow rate=\""))?;
+    let valve_str = valve_str.strip_prefix("Valve ")
+        .ok_or(anyhow!("line should start with \"Valve \""))?;
+    let flow_rate: usize = flow_rate_str.parse()
+        .map_err(|_| anyhow!("bad flow rate {:?}", flow_rate_str))?;
+
+    // tunnels lead to valves DD, II, BB
+    // grammar: there might only be one tunnel
+    let tunnel_str = tunnel_str.strip_prefix("tunnels lead to valves ")
+        .or_else(|| tunnel_str.strip_prefix("tunnel leads to valve "))
+        .ok_or(anyhow!("can't read tunnels from {:?}", tunnel_str))?;
+    let connected = tunnel_str.split(", ").map(|s| s.to_string()).collect();
+
+    Ok((valve_str.to_string(), flow_rate, connected))
+}
+
+// Check the input for everything that would make building the graph panic,
+// and warn about valves we'll never be able to get to
+pub fn validate_input(input: &str) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    // valve name -> (line number, tunnel destinations)
+    let mut valves: HashMap<String, (usize, Vec<String>)> = HashMap::new();
+    // keep the input order so problems come out in line order
+    let mut order = vec![];
+    for (i, line) in input.lines().enumerate() {
+        let line_number = i + 1;
+        match parse_line(line) {
+            Ok((name, _, connected)) => {
+                if let Some((first_line, _)) = valves.get(&name) {
+                    report.errors.push(ValveProblem::DuplicateValve {
+                        line: line_number, name, first_line: *first_line });
+                }
+                else {
+                    order.push(name.clone());
+                    valves.insert(name, (line_number, connected));
+                }
+            },
+            Err(e) => report.errors.push(ValveProblem::Malformed {
+                line: line_number, message: e.to_string() }),
+        }
+    }
+
+    // every tunnel has to go somewhere, and go both ways
+    for name in &order {
+        let (line, connected) = &valves[name];
+        for destination in connected {
+            match valves.get(destination) {
+                None => report.errors.push(ValveProblem::UnknownDestination {
+                    line: *line, from: name.clone(), to: destination.clone() }),
+                Some((_, back)) => if !back.contains(name) {
+                    report.errors.push(ValveProblem::AsymmetricTunnel {
+                        line: *line, from: name.clone(), to: destination.clone() });
+                },
+            }
+        }
+    }
+
+    if !valves.contains_key(START_VALVE) {
+        report.errors.push(ValveProblem::MissingStart);
+        return report;
+    }
+
+    // BFS from the start to find anything we can't reach
+    let mut seen = HashSet::new();
+    seen.insert(START_VALVE);
+    let mut to_visit = vec![START_VALVE];
+    while let Some(name) = to_visit.pop() {
+        for destination in &valves[name].1 {
+            if valves.contains_key(destination) && seen.insert(destination.as_str()) {
+                to_visit.push(destination.as_str());
+            }
+        }
+    }
+    for name in &order {
+        if !seen.contains(name.as_str()) {
+            report.warnings.push(ValveProblem::Unreachable {
+                line: valves[name].0, name: name.clone() });
+        }
+    }
+
+    report
+}
+
 // Format is 
 // Valve AA has flow rate=0; tunnels lead to valves DD, II, BB
 #[aoc_generator(day16)]
@@ -20,6 +159,16 @@ pub fn input_generator(input: &str) -> Result<Data> {
     input_generator_inner(input)
 }
 fn input_generator_inner(input: &str) -> Result<Data> {
+    // check the whole input up front so we don't panic halfway through building the graph
+    let report = validate_input(input);
+    for warning in &report.warnings {
+        eprintln!("day16 warning: {}", warning);
+    }
+    if !report.errors.is_empty() {
+        let messages: Vec<String> = report.errors.iter().map(|e| e.to_string()).collect();
+        return Err(anyhow!("invalid valve input:\n{}", messages.join("\n")));
+    }
+
     // map of valves to their flow rates
     let mut rate_map = HashMap::new();
     // map of valve names to graph indices
@@ -31,27 +180,14 @@ fn input_generator_inner(input: &str) -> Result<Data> {
 
     let mut edges = HashMap::new();
     for line in input.lines() {
-        let (valve_str, tunnel_str) = line.split_once("; ").unwrap();
-
-        // Valve AA has flow rate=0
-        let (valve_str, flow_rate_str) = valve_str.split_once(" has flow rate=").unwrap();
-        let (_, valve_str) = valve_str.split_once(" ").unwrap();
-        let valve_str = valve_str.to_string();
-        let flow_rate:usize = flow_rate_str.parse()?;
+        let (valve_str, flow_rate, connected) = parse_line(line)?;
 
         let node_index = graph.add_node(valve_str.clone());
         rate_map.insert(node_index, flow_rate);
         valve_name_map.insert(valve_str.clone(), node_index);
         valve_index_map.insert(node_index, valve_str.clone());
 
-        // tunnels lead to valves DD, II, BB
-        // grammar: there might only be one tunnel
-        let tunnel_str = match tunnel_str.strip_prefix("tunnels lead to valves ") {
-            Some(many_tunnel) => many_tunnel,
-            None => tunnel_str.strip_prefix("tunnel leads to valve ").unwrap(),
-        };
         // put the names of the destinations since they might not have assigned indices yet
-        let connected: Vec<&str> = tunnel_str.split(", ").collect();
         edges.insert(node_index, connected);
 
     }
@@ -61,27 +197,31 @@ fn input_generator_inner(input: &str) -> Result<Data> {
         // except AA because that's where we start
         let &source_rate = rate_map.get(&source).unwrap();
         let source_name = valve_index_map.get(&source).unwrap();
-        if source_rate == 0 && source_name != "AA" {
+        if source_rate == 0 && source_name != START_VALVE {
             continue;
         }
 
         let destinations = edges.get(&source).unwrap();
         let mut destinations: Vec<(String, Vec<NodeIndex>)> = destinations
-            .iter().map(|&d| (d.to_string(), vec![source])).collect();
+            .iter().map(|d| (d.to_string(), vec![source])).collect();
 
         while !destinations.is_empty() {
             let (destination_name, predecessors) = destinations.pop().unwrap();
             let &destination = valve_name_map.get(&destination_name).unwrap();
             let &dest_rate = rate_map.get(&destination).unwrap();
-            if dest_rate != 0 || destination_name == "AA" {
+            if dest_rate != 0 || destination_name == START_VALVE {
                 // a regular tunnel from source to dest
-                graph.update_edge(source, destination, predecessors.len());
+                // there can be more than one way through the zero-rate valves: keep the shortest
+                match graph.find_edge(source, destination) {
+                    Some(edge) if graph[edge] <= predecessors.len() => (),
+                    _ => { graph.update_edge(source, destination, predecessors.len()); },
+                }
             }
             else {
                 // treat this valve as part of a longer tunnel
                 let mut further_predecessors = predecessors.clone();
                 further_predecessors.push(destination);
-                for &further_name in edges.get(&destination).unwrap() {
+                for further_name in edges.get(&destination).unwrap() {
                     let &further = valve_name_map.get(further_name).unwrap();
                     if !predecessors.contains(&further) {
                         destinations.push((further_name.to_string(), further_predecessors.clone()))
@@ -179,12 +319,204 @@ fn solve_part1_inner(input: &Data) -> usize {
     //dbg!(&rate_map);
 
     // start at valve AA
-    let &cur_node = valve_name_map.get("AA").unwrap();
+    let &cur_node = valve_name_map.get(START_VALVE).unwrap();
     let mut cur_path = vec![];
     dfs(&tunnels, cur_node, &mut cur_path, 0, 30, 
         &rate_map, 0, 0)
 }
 
+// How long branch_and_bound is allowed to keep looking
+#[derive(Debug, Clone, Copy)]
+pub enum SearchBudget {
+    // keep going until the search is exhausted
+    Unlimited,
+    // stop after this much wall-clock time
+    Time(Duration),
+    // stop after looking at this many search nodes
+    Nodes(usize),
+}
+
+// The best plan branch_and_bound found
+#[derive(Debug)]
+pub struct SearchResult {
+    // total pressure released by the end
+    pub pressure: usize,
+    // valves in the order they're opened, with the minute each one was opened
+    pub plan: Vec<(String, usize)>,
+    // false if the budget ran out before the search finished
+    pub proven_optimal: bool,
+    // how many search nodes were looked at
+    pub nodes: usize,
+}
+
+// State for the branch and bound search
+// Valve 0 is the start, the rest are the valves with nonzero flow rates
+struct BranchAndBound {
+    rates: Vec<usize>,
+    // shortest distance between every pair of valves
+    distances: Vec<Vec<usize>>,
+    // shortest distance between any two different valves, for the bound
+    min_distance: usize,
+    // valves sorted by descending flow rate, for the bound
+    by_rate: Vec<usize>,
+    budget: SearchBudget,
+    started: Instant,
+    nodes: usize,
+    out_of_budget: bool,
+    opened: Vec<bool>,
+    cur_plan: Vec<(usize, usize)>,
+    best_pressure: usize,
+    best_plan: Vec<(usize, usize)>,
+}
+
+impl BranchAndBound {
+    // Optimistic guess of how much more pressure we could release:
+    // pretend every closed valve is min_distance away from the last one,
+    // and open the biggest ones first
+    fn upper_bound(&self, time_left: usize) -> usize {
+        let mut bound = 0;
+        let mut time_left = time_left;
+        for &valve in &self.by_rate {
+            if self.opened[valve] {
+                continue;
+            }
+            if time_left <= self.min_distance + 1 {
+                break;
+            }
+            time_left -= self.min_distance + 1;
+            bound += self.rates[valve] * time_left;
+        }
+        bound
+    }
+
+    fn budget_exceeded(&self) -> bool {
+        match self.budget {
+            SearchBudget::Unlimited => false,
+            SearchBudget::Time(limit) => self.started.elapsed() >= limit,
+            SearchBudget::Nodes(limit) => self.nodes >= limit,
+        }
+    }
+
+    fn search(&mut self, cur_valve: usize, cur_step: usize, max_steps: usize, pressure: usize) {
+        if self.out_of_budget {
+            return;
+        }
+        if self.budget_exceeded() {
+            self.out_of_budget = true;
+            return;
+        }
+        self.nodes += 1;
+
+        if pressure > self.best_pressure {
+            self.best_pressure = pressure;
+            self.best_plan = self.cur_plan.clone();
+        }
+
+        // nothing down here can beat what we already have
+        let time_left = max_steps - cur_step;
+        if pressure + self.upper_bound(time_left) <= self.best_pressure {
+            return;
+        }
+
+        // try the valves that would release the most pressure right away first,
+        // so good plans turn up early if we run out of budget
+        let mut next_valves: Vec<(usize, usize)> = (1..self.rates.len())
+            .filter(|&valve| !self.opened[valve])
+            .filter(|&valve| self.distances[cur_valve][valve] + 1 < time_left)
+            .map(|valve| {
+                let opened_at = cur_step + self.distances[cur_valve][valve] + 1;
+                (valve, opened_at)
+            })
+            .collect();
+        next_valves.sort_by_key(|&(valve, opened_at)| std::cmp::Reverse(self.rates[valve] * (max_steps - opened_at)));
+
+        for (valve, opened_at) in next_valves {
+            let gained = self.rates[valve] * (max_steps - opened_at);
+            self.opened[valve] = true;
+            self.cur_plan.push((valve, opened_at));
+            self.search(valve, opened_at, max_steps, pressure + gained);
+            self.cur_plan.pop();
+            self.opened[valve] = false;
+        }
+    }
+}
+
+// Branch and bound search for the most pressure released in max_steps minutes
+// Stops early if the budget runs out, returning the best plan found so far
+pub fn branch_and_bound(input: &Data, max_steps: usize, budget: SearchBudget) -> SearchResult {
+    let (rate_map, valve_name_map, tunnels) = input;
+
+    // only the start and the valves worth opening matter
+    // (and only the ones we can actually get to)
+    let &start = valve_name_map.get(START_VALVE).unwrap();
+    let from_start = dijkstra(tunnels, start, None, |e| *e.weight());
+    let mut valves = vec![start];
+    let mut useful: Vec<NodeIndex> = rate_map.iter()
+        .filter(|&(valve, &rate)| rate > 0 && from_start.contains_key(valve))
+        .map(|(&valve, _)| valve)
+        .collect();
+    useful.sort();
+    valves.extend(useful);
+    let rates: Vec<usize> = valves.iter().map(|valve| rate_map[valve]).collect();
+
+    // shortest distances between the valves we care about
+    let mut distances = vec![vec![usize::MAX; valves.len()]; valves.len()];
+    for (i, &from) in valves.iter().enumerate() {
+        let from_here = dijkstra(tunnels, from, None, |e| *e.weight());
+        for (j, to) in valves.iter().enumerate() {
+            if let Some(&d) = from_here.get(to) {
+                distances[i][j] = d;
+            }
+        }
+    }
+    let min_distance = distances.iter().enumerate()
+        .flat_map(|(i, row)| row.iter().enumerate()
+            .filter(move |&(j, _)| i != j)
+            .map(|(_, &d)| d))
+        .min()
+        .unwrap_or(1)
+        .max(1);
+
+    let mut by_rate: Vec<usize> = (1..valves.len()).collect();
+    by_rate.sort_by_key(|&valve| std::cmp::Reverse(rates[valve]));
+
+    let mut search = BranchAndBound {
+        opened: vec![false; valves.len()],
+        rates,
+        distances,
+        min_distance,
+        by_rate,
+        budget,
+        started: Instant::now(),
+        nodes: 0,
+        out_of_budget: false,
+        cur_plan: vec![],
+        best_pressure: 0,
+        best_plan: vec![],
+    };
+    search.search(0, 0, max_steps, 0);
+
+    let plan = search.best_plan.iter()
+        .map(|&(valve, opened_at)| (tunnels[valves[valve]].clone(), opened_at))
+        .collect();
+    SearchResult {
+        pressure: search.best_pressure,
+        plan,
+        proven_optimal: !search.out_of_budget,
+        nodes: search.nodes,
+    }
+}
+
+// Part 1 again, but give up and take the best plan so far after 10 seconds
+#[aoc(day16, part1, branch_and_bound)]
+pub fn solve_part1_branch_and_bound(input: &Data) -> usize {
+    let result = branch_and_bound(input, 30, SearchBudget::Time(Duration::from_secs(10)));
+    if !result.proven_optimal {
+        eprintln!("day16: ran out of time after {} nodes, answer may not be optimal", result.nodes);
+    }
+    result.pressure
+}
+
 #[aoc(day16, part2)]
 pub fn solve_part2(input: &Data) -> usize {
     solve_part2_inner(input)
@@ -215,6 +547,77 @@ Valve JJ has flow rate=21; tunnel leads to valve II
         assert_eq!(result, 1651);
     }
 
+    #[test]
+    fn test_validate_input() {
+        use super::ValveProblem;
+
+        let report = super::validate_input(TEST_INPUT);
+        assert!(report.errors.is_empty());
+        assert!(report.warnings.is_empty());
+
+        let bad_input =
+r#"Valve BB has flow rate=13; tunnels lead to valves CC, ZZ
+Valve CC has flow rate=2; tunnel leads to valve BB
+Valve BB has flow rate=1; tunnel leads to valve CC
+Valve DD has flow rate=20; tunnel leads to valve CC
+Valve EE has flow rate=3
+"#;
+        let report = super::validate_input(bad_input);
+        assert_eq!(report.errors, vec![
+            ValveProblem::DuplicateValve { line: 3, name: "BB".to_string(), first_line: 1 },
+            ValveProblem::Malformed { line: 5,
+                message: "missing \"; \" between valve and tunnels".to_string() },
+            ValveProblem::UnknownDestination { line: 1, from: "BB".to_string(), to: "ZZ".to_string() },
+            ValveProblem::AsymmetricTunnel { line: 4, from: "DD".to_string(), to: "CC".to_string() },
+            ValveProblem::MissingStart,
+        ]);
+        assert!(super::input_generator(bad_input).is_err());
+
+        let unreachable_input =
+r#"Valve AA has flow rate=0; tunnel leads to valve BB
+Valve BB has flow rate=13; tunnel leads to valve AA
+Valve CC has flow rate=2; tunnel leads to valve DD
+Valve DD has flow rate=20; tunnel leads to valve CC
+"#;
+        let report = super::validate_input(unreachable_input);
+        assert!(report.errors.is_empty());
+        assert_eq!(report.warnings, vec![
+            ValveProblem::Unreachable { line: 3, name: "CC".to_string() },
+            ValveProblem::Unreachable { line: 4, name: "DD".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_branch_and_bound() {
+        use super::SearchBudget;
+
+        let input = super::input_generator(TEST_INPUT).unwrap();
+        let result = super::branch_and_bound(&input, 30, SearchBudget::Unlimited);
+        assert_eq!(result.pressure, 1651);
+        assert!(result.proven_optimal);
+        let opened: Vec<&str> = result.plan.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(opened, vec!["DD", "BB", "JJ", "HH", "EE", "CC"]);
+        assert_eq!(result.plan[0].1, 2);
+
+        let result = super::branch_and_bound(&input, 30, SearchBudget::Nodes(3));
+        assert!(!result.proven_optimal);
+        assert!(result.pressure <= 1651);
+        assert_eq!(result.nodes, 3);
+
+        // valves we can't get to are just ignored
+        let unreachable_input =
+r#"Valve AA has flow rate=0; tunnel leads to valve BB
+Valve BB has flow rate=13; tunnel leads to valve AA
+Valve CC has flow rate=2; tunnel leads to valve DD
+Valve DD has flow rate=20; tunnel leads to valve CC
+"#;
+        let input = super::input_generator(unreachable_input).unwrap();
+        let result = super::branch_and_bound(&input, 30, SearchBudget::Unlimited);
+        assert_eq!(result.pressure, 13 * 28);
+        assert!(result.proven_optimal);
+        assert_eq!(result.plan, vec![("BB".to_string(), 2)]);
+    }
+
     #[test]
     fn test_part2_example() {
         let input = super::input_generator(TEST_INPUT).unwrap();
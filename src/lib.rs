@@ -11,10 +11,10 @@ mod day8;
 mod day9;
 mod day10;
 mod day11;
-mod day12;
-mod day13;
-mod day14;
-mod day15;
-mod day16;
+pub mod day12;
+pub mod day13;
+pub mod day14;
+pub mod day15;
+pub mod day16;
 
 aoc_lib! { year = 2022 }
\ No newline at end of file